@@ -1,4 +1,5 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -8,7 +9,16 @@ use clap::Parser;
 )]
 pub enum Command {
     /// Applies all pending migrations.
-    Up,
+    Up {
+        /// With several databases or tenant schemas, stop at the first one that fails instead
+        /// of carrying on with the rest.
+        #[clap(long, default_value_t = false)]
+        fail_fast: bool,
+
+        /// With several databases, how many to migrate at the same time.
+        #[clap(short, long, default_value_t = 1)]
+        jobs: usize,
+    },
 
     /// Rolls back the last migration.
     Down {
@@ -25,17 +35,139 @@ pub enum Command {
         /// The name of the migration to roll back. If not provided, the default is to select
         /// the latest non-pending migration.
         name: Option<String>,
+
+        /// With several databases or tenant schemas, stop at the first one that fails instead
+        /// of carrying on with the rest.
+        #[clap(long, default_value_t = false)]
+        fail_fast: bool,
+
+        /// Don't ask for confirmation when the database is protected.
+        #[clap(short, long, default_value_t = false)]
+        yes: bool,
+    },
+
+    /// Rolls back the last migration and applies it again.
+    Redo {
+        /// If the migration is changed, roll it back using the down sql stored in the database,
+        /// then apply the file. Cannot be used with `--ignore-changed`.
+        #[clap(short, long, default_value_t = false)]
+        recover: bool,
+
+        /// If the migration is changed, run the down sql defined in the migration file.
+        /// Cannot be used with `--recover`.
+        #[clap(short, long, default_value_t = false)]
+        ignore_changed: bool,
+
+        /// The name of the migration to redo. If not provided, the default is to select the
+        /// latest non-pending migration.
+        name: Option<String>,
+
+        /// With several databases or tenant schemas, stop at the first one that fails instead
+        /// of carrying on with the rest.
+        #[clap(long, default_value_t = false)]
+        fail_fast: bool,
+
+        /// Don't ask for confirmation when the database is protected.
+        #[clap(short, long, default_value_t = false)]
+        yes: bool,
     },
 
     /// Prints the current status of the database.
     Status,
 
+    /// Prints a migration's state, when it was applied, and its sql, with a diff between the
+    /// sql stored in the database and the file's if it has changed.
+    Show {
+        /// The name of the migration.
+        name: String,
+    },
+
     /// Creates a new migration file.
     New {
         /// The name to use for the migration file, e.g., "create-users"
         name: String,
     },
 
+    /// Checks migrations for statements that are risky to run against a live database.
+    Lint {
+        /// The lowest severity that makes the command fail.
+        #[clap(long, value_enum, default_value_t = FailOn::Warning)]
+        fail_on: FailOn,
+
+        /// The names of the migrations to check. If not provided, all migrations are checked.
+        names: Vec<String>,
+    },
+
+    /// Prints the recorded history of applies, rollbacks, repairs and baselines.
+    History {
+        /// Only show events for this migration.
+        name: Option<String>,
+    },
+
+    /// Updates the sql stored in the database for changed migrations to match their files,
+    /// without running anything.
+    Repair {
+        /// The name of the migration to repair. If not provided, all changed migrations are
+        /// repaired.
+        name: Option<String>,
+
+        /// Don't ask for confirmation when the database is protected.
+        #[clap(short, long, default_value_t = false)]
+        yes: bool,
+    },
+
+    /// Marks pending migrations up to and including the given one as applied, without
+    /// running them.
+    Baseline {
+        /// The name of the last migration to mark as applied.
+        name: String,
+
+        /// Don't ask for confirmation when the database is protected.
+        #[clap(short, long, default_value_t = false)]
+        yes: bool,
+    },
+
+    /// Marks migrations that another migration tool applied, according to its own table, as
+    /// applied, without running them.
+    Import {
+        /// The tool whose table to read.
+        #[clap(long, value_enum)]
+        from: ImportFrom,
+    },
+
+    /// Converts another migration tool's migration files into fly migrations in MIGRATE_DIR.
+    Convert {
+        /// The tool whose files to convert.
+        #[clap(long, value_enum)]
+        from: ConvertFrom,
+
+        /// The directory holding the files.
+        dir: PathBuf,
+    },
+
     /// Outputs the contents of an example .env file to use with fly.
     ExampleEnv,
 }
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ConvertFrom {
+    GolangMigrate,
+    Dbmate,
+    Sqlx,
+    Flyway,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ImportFrom {
+    Rails,
+    Diesel,
+    Sqlx,
+    Flyway,
+    Goose,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum FailOn {
+    Warning,
+    Error,
+}
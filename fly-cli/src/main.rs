@@ -1,12 +1,29 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
-use command::Command;
-use fly::db::Db;
+use command::{Command, ConvertFrom, FailOn, ImportFrom};
+use fly::config::Config;
+use fly::convert::{self, Format};
+use fly::error::Error;
+use fly::file;
+use fly::history::HistoryEntry;
+use fly::import::Tool;
+use fly::lint::{lint, Severity};
+use fly::migration::{Migration, MigrationMeta, Section};
+use fly::migrator::{DownOptions, Migrator};
 use fly::planner::ApplicationState;
-use fly::{config::Config, planner::get_all_migration_state};
-use std::process::exit;
-use std::{io::Write, time::SystemTime};
-use tracing::{debug, error, info, Level};
+use fly::template;
+use similar::TextDiff;
+use std::collections::BTreeSet;
+use std::iter::once;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::{
+    io::{IsTerminal, Write},
+    time::SystemTime,
+};
+use tracing::{debug, error, info, info_span, warn, Level};
 
 mod command;
 
@@ -21,17 +38,462 @@ PG_PASSWORD=1234
 PG_DB=db
 ";
 
-fn startup() -> Result<(Db, Vec<ApplicationState>)> {
-    let config = Config::from_env()?;
-    let mut db = Db::connect(&config).context("couldn't connect to database")?;
-    db.create_migrations_table()
-        .context("failed creating migrations table")?;
-    let application_state = get_all_migration_state(&mut db, &config.migrate_dir)?;
+// Exit codes, documented in the README. Usage errors exit with 2, from clap.
+const EXIT_FAILURE: u8 = 1;
+const EXIT_CONNECTION: u8 = 3;
+const EXIT_MIGRATION_FAILED: u8 = 4;
+const EXIT_BOOKKEEPING: u8 = 5;
+const EXIT_LOCK_TIMEOUT: u8 = 6;
+const EXIT_DRIFT: u8 = 7;
+const EXIT_ROLLBACK_PENDING: u8 = 8;
+const EXIT_LINT: u8 = 9;
 
-    Ok((db, application_state))
+// The exit code for the first fly error in `error`'s chain.
+fn exit_code(error: &anyhow::Error) -> u8 {
+    let Some(error) = error.chain().find_map(|e| e.downcast_ref::<Error>()) else {
+        return EXIT_FAILURE;
+    };
+    match error {
+        Error::Connection(_) => EXIT_CONNECTION,
+        Error::MigrationStatementFailed(_) | Error::MigrationCommitFailed { .. } => {
+            EXIT_MIGRATION_FAILED
+        }
+        Error::Bookkeeping { .. } | Error::UnexpectedRowCount { .. } => EXIT_BOOKKEEPING,
+        Error::LockTimeout { .. } => EXIT_LOCK_TIMEOUT,
+        Error::MigrationChanged { .. } | Error::MigrationRemoved { .. } => EXIT_DRIFT,
+        Error::RollbackPending { .. } => EXIT_ROLLBACK_PENDING,
+        _ => EXIT_FAILURE,
+    }
+}
+
+// Connects to `config`'s database and runs `f` against it. With
+// MIGRATE_DATABASES, each database is migrated in turn, up to `jobs` at a
+// time, and in multi-tenant mode each tenant schema of each database. `f`
+// is given a label for the target, like `shard1/tenant_a`. A target's
+// failure is logged and the rest still run, unless `fail_fast` is set. The
+// returned error is caused by the first failure, so it exits with its code.
+// With a `confirmation`, each protected database is confirmed once, before
+// any of its schemas are touched.
+fn for_each_target(
+    config: Config,
+    jobs: usize,
+    fail_fast: bool,
+    confirmation: Option<&Confirmation>,
+    f: impl Fn(&str, &mut Migrator) -> Result<()> + Sync,
+) -> Result<()> {
+    if config.databases.is_empty() && config.tenants.is_none() {
+        let mut migrator = Migrator::connect(config)?;
+        if let Some(confirmation) = confirmation {
+            confirm(&mut migrator, &[None], confirmation)?;
+        }
+        return f("", &mut migrator);
+    }
+
+    let failed = Mutex::new(Vec::new());
+    let total = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let fail = |label: String, e: anyhow::Error| {
+        error!("{:#}", e);
+        failed.lock().unwrap().push((label, e));
+        if fail_fast {
+            stop.store(true, Ordering::SeqCst);
+        }
+    };
+    let run_database = |name: Option<&str>, config: Config| {
+        let label = |schema: Option<&str>| {
+            [name, schema]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join("/")
+        };
+        let has_tenants = config.tenants.is_some();
+        let schemas = Migrator::connect(config)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut migrator| {
+                let schemas = if has_tenants {
+                    migrator
+                        .schemas()
+                        .context("couldn't list tenant schemas")?
+                        .into_iter()
+                        .map(Some)
+                        .collect()
+                } else {
+                    vec![None]
+                };
+                if let Some(confirmation) = confirmation {
+                    confirm(&mut migrator, &schemas, confirmation)?;
+                }
+                Ok((migrator, schemas))
+            });
+        let (mut migrator, schemas) = match schemas {
+            Ok(found) => found,
+            Err(e) => {
+                total.fetch_add(1, Ordering::SeqCst);
+                return fail(label(None), e);
+            }
+        };
+        for schema in &schemas {
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+            total.fetch_add(1, Ordering::SeqCst);
+            let _span = schema
+                .as_ref()
+                .map(|schema| info_span!("tenant", schema = %schema).entered());
+            let result = schema
+                .as_ref()
+                .map_or(Ok(()), |schema| migrator.set_schema(schema))
+                .map_err(anyhow::Error::from)
+                .and_then(|_| f(&label(schema.as_deref()), &mut migrator));
+            if let Err(e) = result {
+                fail(label(schema.as_deref()), e);
+            }
+        }
+    };
+
+    if config.databases.is_empty() {
+        run_database(None, config.clone());
+    } else {
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..jobs.clamp(1, config.databases.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    if i >= config.databases.len() || stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let database = &config.databases[i];
+                    let _span = info_span!("database", name = %database.name).entered();
+                    run_database(Some(&database.name), config.for_database(database));
+                });
+            }
+        });
+    }
+
+    let mut failed = failed.into_inner().unwrap();
+    if failed.is_empty() {
+        return Ok(());
+    }
+    let labels = failed
+        .iter()
+        .map(|(label, _)| label.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let count = failed.len();
+    let (first, error) = failed.swap_remove(0);
+    if fail_fast {
+        return Err(error.context(format!("aborting after failure in {}", first)));
+    }
+    Err(error.context(format!(
+        "failed in {} of {} targets: {}",
+        count,
+        total.into_inner(),
+        labels
+    )))
+}
+
+// e.g. "applied 2024-03-23T12:00:00Z by alice@build-01 with fly 0.2.1 in 35ms"
+fn describe_application(meta: &MigrationMeta) -> String {
+    let unknown = || "unknown".to_string();
+    format!(
+        "applied {} by {}@{} with fly {} in {}",
+        humantime::format_rfc3339_seconds(meta.created_at),
+        meta.applied_by.clone().unwrap_or_else(unknown),
+        meta.applied_host.clone().unwrap_or_else(unknown),
+        meta.fly_version.clone().unwrap_or_else(unknown),
+        meta.execution_time
+            .map(|d| format!("{}ms", d.as_millis()))
+            .unwrap_or_else(unknown),
+    )
+}
+
+// e.g. "2024-03-23T12:00:00Z rollback 1711200000-create-users.sql by alice@build-01 with fly 0.2.1 (sha256 3f2a9c1e0b7d)"
+fn describe_history_entry(entry: &HistoryEntry) -> String {
+    let unknown = || "unknown".to_string();
+    format!(
+        "{} {} {} by {}@{} with fly {} (sha256 {})",
+        humantime::format_rfc3339_seconds(entry.created_at),
+        entry.event,
+        entry.name,
+        entry.applied_by.clone().unwrap_or_else(unknown),
+        entry.applied_host.clone().unwrap_or_else(unknown),
+        entry.fly_version.clone().unwrap_or_else(unknown),
+        entry.checksum.get(..12).unwrap_or(&entry.checksum),
+    )
+}
+
+fn up(migrator: &mut Migrator) -> Result<()> {
+    if migrator.up()?.is_empty() {
+        info!("database is up to date");
+    }
+    Ok(())
+}
+
+// A destructive command that must be confirmed against protected databases:
+// what it does, and how to plan it in the current target.
+struct Confirmation<'a> {
+    action: &'a str,
+    yes: bool,
+    plan: &'a (dyn Fn(&mut Migrator) -> Result<Vec<String>> + Sync),
+}
+
+// Before a destructive command against a protected database, prints what it
+// will do in each of `schemas` and asks for the database's name to be typed,
+// unless --yes was passed. Without a terminal to ask on, or a name to ask
+// for, refuses instead.
+fn confirm(
+    migrator: &mut Migrator,
+    schemas: &[Option<String>],
+    confirmation: &Confirmation,
+) -> Result<()> {
+    if !migrator.config().is_protected() {
+        return Ok(());
+    }
+    let mut plan = vec![];
+    for schema in schemas {
+        if let Some(schema) = schema {
+            migrator.set_schema(schema)?;
+        }
+        plan.extend(
+            (confirmation.plan)(migrator)?
+                .into_iter()
+                .map(|name| match schema {
+                    Some(schema) => format!("{}: {}", schema, name),
+                    None => name,
+                }),
+        );
+    }
+    if plan.is_empty() {
+        return Ok(());
+    }
+    let action = confirmation.action;
+    let database = migrator.config().database_name();
+    warn!(
+        "{} is protected. About to {}:",
+        database.as_deref().unwrap_or("the database"),
+        action
+    );
+    for name in &plan {
+        warn!("    {}", name);
+    }
+    if confirmation.yes {
+        return Ok(());
+    }
+    let Some(database) = database else {
+        bail!(
+            "the protected database has no name to confirm with, pass --yes to {} without confirming",
+            action
+        );
+    };
+    if !std::io::stdin().is_terminal() {
+        bail!(
+            "{} is protected, pass --yes to {} without confirming",
+            database,
+            action
+        );
+    }
+    eprint!("Type the database name to continue: ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if answer.trim() != database {
+        bail!("{} didn't match the database name, aborting", answer.trim());
+    }
+    Ok(())
+}
+
+// Suggests --recover when the migration to roll back has changed or is gone.
+fn with_recover_hint(e: anyhow::Error) -> anyhow::Error {
+    match e.downcast_ref::<Error>() {
+        Some(Error::MigrationChanged { .. } | Error::MigrationRemoved { .. }) => e.context(
+            "rollback aborted. Use the --recover flag to run the down sql stored in the database.",
+        ),
+        _ => e,
+    }
+}
+
+fn down(migrator: &mut Migrator, options: &DownOptions) -> Result<()> {
+    let report = migrator
+        .down(options)
+        .map_err(|e| with_recover_hint(e.into()))?;
+    if report.reverted.is_none() {
+        info!("no migrations to revert");
+    }
+    Ok(())
+}
+
+fn redo(migrator: &mut Migrator, options: &DownOptions) -> Result<()> {
+    let report = migrator
+        .redo(options)
+        .map_err(|e| with_recover_hint(e.into()))?;
+    if report.redone.is_none() {
+        info!("no migrations to redo");
+    }
+    Ok(())
+}
+
+fn status(migrator: &mut Migrator) -> Result<()> {
+    for application in &migrator.status()? {
+        info!("{}", application);
+        if let Some(applied) = application.application() {
+            info!("    {}", describe_application(&applied.meta));
+        }
+        debug!("{:?}", application);
+    }
+    Ok(())
+}
+
+// Prints each migration's state in each target, e.g.
+//
+//   migration                     shard1   shard2
+//   1711200000-create-users.sql   applied  applied
+//   1711200001-create-posts.sql   applied  pending
+fn print_matrix(columns: &[(String, Vec<ApplicationState>)]) {
+    let rows = columns
+        .iter()
+        .flat_map(|(_, states)| states.iter().map(|s| (s.kind(), s.name())))
+        .collect::<BTreeSet<_>>();
+    let name_width = rows
+        .iter()
+        .map(|(_, name)| name.len())
+        .chain(once("migration".len()))
+        .max()
+        .unwrap_or_default();
+    let widths = columns
+        .iter()
+        .map(|(label, _)| label.len().max("applied".len()))
+        .collect::<Vec<_>>();
+
+    let mut header = format!("{:name_width$}", "migration");
+    for ((label, _), width) in columns.iter().zip(&widths) {
+        header.push_str(&format!("  {:width$}", label));
+    }
+    info!("{}", header.trim_end());
+    for (_, name) in rows {
+        let mut line = format!("{:name_width$}", name);
+        for ((_, states), width) in columns.iter().zip(&widths) {
+            let status = states
+                .iter()
+                .find(|s| s.name() == name)
+                .map_or("-", |s| s.short_status());
+            line.push_str(&format!("  {:width$}", status));
+        }
+        info!("{}", line.trim_end());
+    }
+}
+
+// Prints to stdout rather than logging like the other commands, so that the
+// sql can be piped or redirected as it is, without log formatting.
+fn show(migrator: &mut Migrator, label: &str, name: &str) -> Result<()> {
+    let application_state = migrator.status()?;
+    let application = application_state
+        .iter()
+        .find(|application| application.name() == name)
+        .ok_or_else(|| Error::MigrationNotFound {
+            name: name.to_string(),
+        })?;
+    if !label.is_empty() {
+        println!("{}:", label);
+    }
+    println!("{}", application);
+    println!("kind: {}", application.kind().as_str());
+    if let Some(applied) = application.application() {
+        println!("{}", describe_application(&applied.meta));
+    }
+    match application {
+        ApplicationState::Pending { definition } | ApplicationState::Applied { definition, .. } => {
+            print_sql(definition)
+        }
+        ApplicationState::Removed { application } => {
+            println!("\nsql stored in the database:");
+            print_sql(&application.migration);
+        }
+        ApplicationState::Changed {
+            definition,
+            application,
+        } => {
+            println!("\nsql in the file:");
+            print_sql(definition);
+            println!("\nsql stored in the database:");
+            print_sql(&application.migration);
+            for section in [Section::Up, Section::Down] {
+                let stored = format!("{}\n", application.migration.sql(section));
+                let file = format!("{}\n", definition.sql(section));
+                if stored == file {
+                    println!("\n{} sql is unchanged", section);
+                    continue;
+                }
+                let diff = TextDiff::from_lines(&stored, &file);
+                print!(
+                    "\n{}",
+                    diff.unified_diff().header(
+                        &format!("database ({})", section),
+                        &format!("file ({})", section)
+                    )
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+// Prints a migration's sql as it would appear in its file.
+fn print_sql(migration: &Migration) {
+    if migration.is_repeatable() {
+        println!("\n{}", migration.up_sql);
+    } else {
+        println!(
+            "\n-- up\n{}\n\n-- down\n{}",
+            migration.up_sql, migration.down_sql
+        );
+    }
+}
+
+fn history(migrator: &mut Migrator, name: Option<&str>) -> Result<()> {
+    let history = migrator.history(name)?;
+    if history.is_empty() {
+        info!("no history recorded");
+    }
+    for entry in &history {
+        info!("{}", describe_history_entry(entry));
+        debug!("{:?}", entry);
+    }
+    Ok(())
 }
 
-fn main() -> Result<()> {
+fn repair(migrator: &mut Migrator, name: Option<&str>) -> Result<()> {
+    if migrator.repair(name)?.is_empty() {
+        info!("no changed migrations to repair");
+    }
+    Ok(())
+}
+
+fn import(migrator: &mut Migrator, tool: Tool) -> Result<()> {
+    let report = migrator.import(tool)?;
+    for version in &report.unmatched {
+        warn!(
+            "{} applied version {}, which has no migration",
+            tool, version
+        );
+    }
+    if report.imported.is_empty() {
+        info!("no pending migrations to import");
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+fn run() -> Result<ExitCode> {
     dotenv::dotenv().ok();
 
     let debug = std::env::var("DEBUG").unwrap_or("false".to_string()) == "true";
@@ -41,6 +503,7 @@ fn main() -> Result<()> {
     let subscriber = tracing_subscriber::FmtSubscriber::builder()
         .without_time()
         .with_target(false)
+        .with_ansi(std::io::stdout().is_terminal())
         .with_max_level(level)
         .finish();
 
@@ -48,99 +511,88 @@ fn main() -> Result<()> {
         .context("setting tracing subscriber failed")?;
 
     match command {
-        Command::Up => {
-            let (mut db, application_state) = startup()?;
-            let mut any_migrations_run = false;
-            for application in &application_state {
-                match application {
-                    ApplicationState::Pending { definition } => {
-                        info!("applying {}", definition.name);
-                        debug!("{}", definition.up_sql);
-                        db.run(definition)?;
-                        any_migrations_run = true;
-                    }
-                    _ => continue,
-                }
-            }
-            if !any_migrations_run {
-                info!("database is up to date");
-            }
+        Command::Up { fail_fast, jobs } => {
+            for_each_target(Config::from_env()?, jobs, fail_fast, None, |_, migrator| {
+                up(migrator)
+            })?
         }
         Command::Down {
             recover,
             ignore_changed,
             name,
+            fail_fast,
+            yes,
         } => {
-            let (mut db, application_state) = startup()?;
-            if recover && ignore_changed {
-                error!("cannot specify both --recover and --ignore-changed, aborting");
-                exit(1);
-            }
-            let migration = if let Some(name) = name {
-                match application_state
-                    .iter()
-                    .find(|application| application.name() == name)
-                {
-                    Some(s) => Some(s),
-                    None => {
-                        error!("couldn't find migration {}", name);
-                        exit(1);
-                    }
-                }
-            } else {
-                application_state
-                    .iter()
-                    .rfind(|application| !application.is_pending())
+            let options = DownOptions {
+                name,
+                recover,
+                ignore_changed,
             };
-            match migration {
-                Some(application) => match application {
-                    ApplicationState::Applied {
-                        definition,
-                        application: _,
-                    } => {
-                        debug!(definition.down_sql);
-                        info!("reverting {}", definition.name);
-                        db.rollback_migration(definition)?;
-                    }
-                    ApplicationState::Changed {
-                        definition,
-                        application,
-                    } => {
-                        let rollback = if recover {
-                            &application.migration
-                        } else if ignore_changed {
-                            definition
-                        } else {
-                            error!("{} has changed, aborting. Use the --recover flag to run the down sql stored in the database.", application.migration.name);
-                            exit(1)
-                        };
-                        debug!("{}", rollback.down_sql);
-                        info!("reverting {}", rollback.name);
-                        db.rollback_migration(rollback)?;
-                    }
-                    ApplicationState::Removed { application } => {
-                        if recover {
-                            debug!("{}", application.migration.down_sql);
-                            info!("reverting application {}", application.migration.name);
-                            db.rollback_migration(&application.migration)?;
-                        } else {
-                            error!("{} was removed, aborting. Use the --recover flag to run the down sql stored in the database.", application.migration.name);
-                            exit(1);
-                        }
-                    }
-                    ApplicationState::Pending { definition } => {
-                        error!("can't roll back a pending migration {}", definition.name);
-                        exit(1);
-                    }
-                },
-                None => info!("no migrations to revert"),
-            }
+            let plan = |migrator: &mut Migrator| {
+                let plan = migrator.plan_down(&options);
+                Ok(Vec::from_iter(
+                    plan.map_err(|e| with_recover_hint(e.into()))?,
+                ))
+            };
+            let confirmation = Confirmation {
+                action: "roll back",
+                yes,
+                plan: &plan,
+            };
+            for_each_target(
+                Config::from_env()?,
+                1,
+                fail_fast,
+                Some(&confirmation),
+                |_, migrator| down(migrator, &options),
+            )?
+        }
+        Command::Redo {
+            recover,
+            ignore_changed,
+            name,
+            fail_fast,
+            yes,
+        } => {
+            let options = DownOptions {
+                name,
+                recover,
+                ignore_changed,
+            };
+            let plan = |migrator: &mut Migrator| {
+                let plan = migrator.plan_down(&options);
+                Ok(Vec::from_iter(
+                    plan.map_err(|e| with_recover_hint(e.into()))?,
+                ))
+            };
+            let confirmation = Confirmation {
+                action: "redo",
+                yes,
+                plan: &plan,
+            };
+            for_each_target(
+                Config::from_env()?,
+                1,
+                fail_fast,
+                Some(&confirmation),
+                |_, migrator| redo(migrator, &options),
+            )?
         }
         Command::Status => {
-            let (_, application_state) = startup()?;
-            for application in &application_state {
-                info!("{}", application);
-                debug!("{:?}", application);
+            let config = Config::from_env()?;
+            if config.databases.is_empty() {
+                for_each_target(config, 1, false, None, |_, migrator| status(migrator))?
+            } else {
+                // One column per database (and schema), filled in as each is
+                // read, then printed together.
+                let columns = Mutex::new(Vec::new());
+                let result = for_each_target(config, 1, false, None, |label, migrator| {
+                    let state = migrator.status()?;
+                    columns.lock().unwrap().push((label.to_string(), state));
+                    Ok(())
+                });
+                print_matrix(&columns.into_inner().unwrap());
+                result?
             }
         }
         Command::New { name } => {
@@ -150,13 +602,145 @@ fn main() -> Result<()> {
                 .expect("time went backwards")
                 .as_secs();
             let filename = format!("{}-{}.sql", timestamp, name);
+            config.naming.check_name(&filename, true)?;
             let path = config.migrate_dir.join(filename);
             let mut file = std::fs::File::create(&path)?;
             file.write_all(MIGRATION_TEMPLATE.as_bytes())?;
             info!("Created file {}", path.display());
         }
+        Command::Show { name } => {
+            for_each_target(Config::from_env()?, 1, false, None, |label, migrator| {
+                show(migrator, label, &name)
+            })?
+        }
+        Command::History { name } => {
+            for_each_target(Config::from_env()?, 1, false, None, |_, migrator| {
+                history(migrator, name.as_deref())
+            })?
+        }
+        Command::Repair { name, yes } => {
+            let plan = |migrator: &mut Migrator| Ok(migrator.plan_repair(name.as_deref())?);
+            let confirmation = Confirmation {
+                action: "repair",
+                yes,
+                plan: &plan,
+            };
+            for_each_target(
+                Config::from_env()?,
+                1,
+                false,
+                Some(&confirmation),
+                |_, migrator| repair(migrator, name.as_deref()),
+            )?
+        }
+        Command::Baseline { name, yes } => {
+            let plan = |migrator: &mut Migrator| Ok(migrator.plan_baseline(&name)?);
+            let confirmation = Confirmation {
+                action: "baseline",
+                yes,
+                plan: &plan,
+            };
+            for_each_target(
+                Config::from_env()?,
+                1,
+                false,
+                Some(&confirmation),
+                |_, migrator| {
+                    migrator.baseline(&name)?;
+                    Ok(())
+                },
+            )?
+        }
+        Command::Lint { fail_on, names } => {
+            // Linting doesn't touch the database, so it can run in CI without
+            // database credentials.
+            let mut config = Config::from_env_offline()?;
+            // `${schema}` is set per tenant schema when migrating, and any
+            // name stands in for it when linting.
+            config
+                .template_vars
+                .entry("schema".to_string())
+                .or_insert_with(|| "tenant".to_string());
+            let migrations = file::list(&config.migrate_dir)?
+                .into_iter()
+                .map(|m| template::render_migration(m, &config.template_vars))
+                .collect::<Result<Vec<_>, _>>()?;
+            for name in &names {
+                if !migrations.iter().any(|m| &m.name == name) {
+                    return Err(Error::MigrationNotFound { name: name.clone() }.into());
+                }
+            }
+            let fail_on = match fail_on {
+                FailOn::Warning => Severity::Warning,
+                FailOn::Error => Severity::Error,
+            };
+            let mut failed = false;
+            let mut count = 0;
+            for migration in &migrations {
+                if !names.is_empty() && !names.contains(&migration.name) {
+                    continue;
+                }
+                for finding in lint(migration) {
+                    match finding.rule.severity {
+                        Severity::Warning => warn!("{}", finding),
+                        Severity::Error => error!("{}", finding),
+                    }
+                    failed |= finding.rule.severity >= fail_on;
+                    count += 1;
+                }
+            }
+            if count == 0 {
+                info!("no problems found");
+            } else {
+                info!("found {} problem(s)", count);
+            }
+            if failed {
+                return Ok(ExitCode::from(EXIT_LINT));
+            }
+        }
+        Command::Import { from } => {
+            let tool = match from {
+                ImportFrom::Rails => Tool::Rails,
+                ImportFrom::Diesel => Tool::Diesel,
+                ImportFrom::Sqlx => Tool::Sqlx,
+                ImportFrom::Flyway => Tool::Flyway,
+                ImportFrom::Goose => Tool::Goose,
+            };
+            for_each_target(Config::from_env()?, 1, false, None, |_, migrator| {
+                import(migrator, tool)
+            })?
+        }
+        Command::Convert { from, dir } => {
+            // Like linting, converting doesn't need database credentials.
+            let config = Config::from_env_offline()?;
+            let format = match from {
+                ConvertFrom::GolangMigrate => Format::GolangMigrate,
+                ConvertFrom::Dbmate => Format::Dbmate,
+                ConvertFrom::Sqlx => Format::Sqlx,
+                ConvertFrom::Flyway => Format::Flyway,
+            };
+            let converted = convert::convert(format, &dir, config.naming.min_version_digits)?;
+            for path in convert::write(&converted, &config.migrate_dir)? {
+                info!("converted {}", path.display());
+            }
+            let empty_down = converted
+                .iter()
+                .filter(|c| c.empty_down)
+                .map(|c| c.path.display().to_string())
+                .collect::<Vec<_>>();
+            if !empty_down.is_empty() {
+                warn!(
+                    "no down sql found for {} migration(s), written with an empty -- down section: {}",
+                    empty_down.len(),
+                    empty_down.join(", ")
+                );
+            }
+            if converted.is_empty() {
+                info!("no {} migrations found in {}", format, dir.display());
+            }
+        }
         Command::ExampleEnv => println!("{}", EXAMPLE_ENV.trim()),
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
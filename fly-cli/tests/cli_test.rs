@@ -1,12 +1,12 @@
 use anyhow::{anyhow, Result};
 use assert_cmd::assert::Assert;
 use assert_cmd::prelude::*;
-use predicates::prelude::predicate;
+use predicates::prelude::{predicate, PredicateBooleanExt};
 use std::io::Write;
 use std::path::Path;
 use std::{
     fs::{self, OpenOptions},
-    process::Command,
+    process::{Command, Stdio},
 };
 use tempfile::tempdir;
 
@@ -228,10 +228,15 @@ drop table users;
     cmd.current_dir(&workdir);
     let output = cmd.output()?;
     let assert = Assert::new(output);
-    assert.success().stdout(predicate::str::contains(format!(
-        "{} [applied]",
-        migration_name
-    )));
+    assert.success().stdout(
+        predicate::str::contains(format!("{} [applied]", migration_name)).and(
+            predicate::str::is_match(format!(
+                r"applied \S+ by \S+@\S+ with fly {} in \d+ms",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .unwrap(),
+        ),
+    );
 
     let mut cmd = Command::cargo_bin("fly")?;
     cmd.arg("down");
@@ -273,3 +278,1035 @@ drop table users;
 
     Ok(())
 }
+
+#[test]
+fn test_reapplies_changed_repeatable_migrations() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    let repeatable_dir = migrate_dir.join("repeatable");
+    fs::create_dir_all(&repeatable_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    fs::write(
+        migrate_dir.join("1-create-users.sql"),
+        "-- up\ncreate table users (id int, active boolean);\n-- down\ndrop table users cascade;\n",
+    )?;
+    fs::write(
+        repeatable_dir.join("views.sql"),
+        "create or replace view active_users as select id from users where active;\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.current_dir(&workdir);
+    cmd.assert().success().stdout(
+        predicate::str::contains("applying 1-create-users.sql")
+            .and(predicate::str::contains("applying views.sql")),
+    );
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("database is up to date"));
+
+    fs::write(
+        repeatable_dir.join("views.sql"),
+        "create or replace view active_users as select id, active from users where active;\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("status");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("views.sql [changed]"));
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("reapplying views.sql"));
+
+    // Rolling back skips over the repeatable migration.
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("down");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("reverting 1-create-users.sql"));
+
+    Ok(())
+}
+
+#[test]
+fn test_rejects_badly_named_migrations() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    fs::write(
+        migrate_dir.join("fix.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.env("MIGRATE_NAMING", "strict");
+    cmd.current_dir(&workdir);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "invalid migration name fix.sql: must start with a numeric version",
+    ));
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("applying fix.sql"));
+
+    Ok(())
+}
+
+#[test]
+fn test_lint() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    fs::write(
+        workdir.join(".env"),
+        format!("MIGRATE_DIR={}\n", migrate_dir.to_string_lossy()),
+    )?;
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\ncreate index users_id on users (id);\n-- down\ndrop table users;\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("lint");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("no problems found"));
+
+    fs::write(
+        migrate_dir.join("1711200001-index-users.sql"),
+        "-- up\ncreate index users_id_2 on users (id);\n-- down\ndrop index users_id_2;\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("lint");
+    cmd.current_dir(&workdir);
+    cmd.assert().code(9).stdout(predicate::str::contains(
+        "1711200001-index-users.sql:2: warning FLY002 (create-index-not-concurrent)",
+    ));
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("lint").arg("--fail-on").arg("error");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("found 1 problem(s)"));
+
+    Ok(())
+}
+
+#[test]
+fn test_lint_tenant_template() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    fs::write(
+        workdir.join(".env"),
+        format!("MIGRATE_DIR={}\n", migrate_dir.to_string_lossy()),
+    )?;
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- fly:template\n-- up\ncreate table ${schema}.users (id int);\ncreate index users_id on ${schema}.users (id);\n-- down\ndrop table ${schema}.users;\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("lint");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("no problems found"));
+
+    Ok(())
+}
+
+#[test]
+fn test_reports_failing_statement_location() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up
+create table users (id int);
+
+insert into users
+  values (1);
+insert into users
+  values ('not a number');
+
+-- down
+drop table users;
+",
+    )?;
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.current_dir(&workdir);
+    cmd.assert().code(4).stderr(predicate::str::contains(
+        "migration 1711200000-create-users.sql failed at line 7 (statement 3 of the up sql, lines 6-7)",
+    ));
+
+    // The whole migration was rolled back.
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("status");
+    cmd.current_dir(&workdir);
+    cmd.assert().success().stdout(predicate::str::contains(
+        "1711200000-create-users.sql [pending]",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_exit_codes() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    let fly = |args: &[&str]| -> Result<Assert> {
+        let mut cmd = Command::cargo_bin("fly")?;
+        cmd.args(args);
+        cmd.current_dir(&workdir);
+        Ok(cmd.assert())
+    };
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("status");
+    cmd.env("PG_CONNECTION_STRING", "postgres://nobody@127.0.0.1:1/none");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .code(3)
+        .stderr(predicate::str::contains("couldn't connect to database"));
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    fly(&["down", "1711200000-create-users.sql"])?
+        .code(8)
+        .stderr(predicate::str::contains(
+            "can't roll back a pending migration 1711200000-create-users.sql",
+        ));
+
+    fly(&["up"])?.success();
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id bigint);\n-- down\ndrop table users;\n",
+    )?;
+    fly(&["down"])?.code(7).stderr(predicate::str::contains(
+        "1711200000-create-users.sql has changed since it was applied",
+    ));
+
+    // A deferred constraint only fails at COMMIT, after the sql has run.
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    fs::write(
+        migrate_dir.join("1711200001-create-posts.sql"),
+        "-- up\ncreate table posts (id int primary key, parent int references posts deferrable initially deferred);\ninsert into posts values (1, 2);\n-- down\ndrop table posts;\n",
+    )?;
+    fly(&["up"])?.code(4).stderr(predicate::str::contains(
+        "migration 1711200001-create-posts.sql failed at commit: insert or update on table \"posts\" violates foreign key constraint",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_records_history() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    fs::write(
+        migrate_dir.join("1711200001-create-posts.sql"),
+        "-- up\ncreate table posts (id int);\n-- down\ndrop table posts;\n",
+    )?;
+
+    let fly = |args: &[&str]| -> Result<Assert> {
+        let mut cmd = Command::cargo_bin("fly")?;
+        cmd.args(args);
+        cmd.current_dir(&workdir);
+        Ok(cmd.assert())
+    };
+
+    fly(&["baseline", "1711200000-create-users.sql"])?
+        .success()
+        .stdout(predicate::str::contains(
+            "baselining 1711200000-create-users.sql",
+        ));
+    fly(&["up"])?.success();
+    fly(&["down"])?.success();
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id bigint);\n-- down\ndrop table users;\n",
+    )?;
+    fly(&["repair"])?.success().stdout(predicate::str::contains(
+        "repairing 1711200000-create-users.sql",
+    ));
+    fly(&["status"])?.success().stdout(predicate::str::contains(
+        "1711200000-create-users.sql [applied]",
+    ));
+
+    fly(&["history"])?.success().stdout(
+        predicate::str::is_match(
+            "baseline 1711200000-create-users.sql by .*\
+             \n.*apply 1711200001-create-posts.sql by .*\
+             \n.*rollback 1711200001-create-posts.sql by .*\
+             \n.*repair 1711200000-create-users.sql by ",
+        )
+        .unwrap(),
+    );
+    fly(&["history", "1711200001-create-posts.sql"])?
+        .success()
+        .stdout(predicate::str::contains("1711200000-create-users.sql").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_repairs_and_baselines() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    fs::write(
+        migrate_dir.join("1711200001-create-posts.sql"),
+        "-- up\ncreate table posts (id int);\n-- down\ndrop table posts;\n",
+    )?;
+    fs::create_dir(migrate_dir.join("repeatable"))?;
+    fs::write(
+        migrate_dir.join("repeatable/views.sql"),
+        "create or replace view user_ids as select id from users;\n",
+    )?;
+
+    let fly = |args: &[&str]| -> Result<Assert> {
+        let mut cmd = Command::cargo_bin("fly")?;
+        cmd.args(args);
+        cmd.current_dir(&workdir);
+        Ok(cmd.assert())
+    };
+
+    // Baselining marks migrations as applied without running them.
+    database
+        .connect()?
+        .batch_execute("create table users (id int);")?;
+    fly(&["baseline", "views.sql"])?
+        .failure()
+        .stderr(predicate::str::contains(
+            "can't baseline repeatable migration views.sql",
+        ));
+    fly(&["baseline", "1711200000-create-users.sql"])?
+        .success()
+        .stdout(predicate::str::contains(
+            "baselining 1711200000-create-users.sql",
+        ));
+    fly(&["status"])?.success().stdout(
+        predicate::str::contains("1711200000-create-users.sql [applied]").and(
+            predicate::str::contains("1711200001-create-posts.sql [pending]"),
+        ),
+    );
+    fly(&["up"])?.success().stdout(
+        predicate::str::contains("applying 1711200001-create-posts.sql")
+            .and(predicate::str::contains("1711200000-create-users.sql").not()),
+    );
+
+    // Repairing updates the stored sql of changed migrations without
+    // running them.
+    fly(&["repair", "1711200000-create-users.sql"])?
+        .failure()
+        .stderr(predicate::str::contains(
+            "1711200000-create-users.sql has not changed, nothing to repair",
+        ));
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id bigint);\n-- down\ndrop table users;\n",
+    )?;
+    fly(&["status"])?.success().stdout(predicate::str::contains(
+        "1711200000-create-users.sql ** CHANGED **",
+    ));
+    fly(&["repair"])?.success().stdout(predicate::str::contains(
+        "repairing 1711200000-create-users.sql",
+    ));
+    fly(&["status"])?.success().stdout(predicate::str::contains(
+        "1711200000-create-users.sql [applied]",
+    ));
+    let column_type = database.connect()?.query_one(
+        "select data_type from information_schema.columns where table_name = 'users'",
+        &[],
+    )?;
+    assert_eq!(column_type.get::<_, &str>(0), "integer");
+
+    Ok(())
+}
+
+#[test]
+fn test_lock_timeout_and_retries() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.current_dir(&workdir);
+    cmd.assert().success();
+
+    fs::write(
+        migrate_dir.join("1711200001-add-name.sql"),
+        "-- fly:lock-timeout 100ms
+-- up
+alter table users add column name text;
+-- down
+alter table users drop column name;
+",
+    )?;
+
+    // Hold a lock on users, as a long running transaction would.
+    let mut client = database.connect()?;
+    let mut transaction = client.transaction()?;
+    transaction.batch_execute("lock table users in access share mode")?;
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.env("MIGRATE_LOCK_RETRIES", "2");
+    cmd.env("MIGRATE_LOCK_RETRY_BACKOFF", "10ms");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .code(6)
+        .stdout(
+            predicate::str::contains(
+                "1711200001-add-name.sql timed out waiting for a lock, retrying in 10ms (1 of 2)",
+            )
+            .and(predicate::str::contains("retrying in 20ms (2 of 2)")),
+        )
+        .stderr(predicate::str::contains(
+            "1711200001-add-name.sql timed out waiting for a lock after 3 attempt(s)",
+        ));
+
+    transaction.rollback()?;
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("applying 1711200001-add-name.sql"));
+
+    Ok(())
+}
+
+#[test]
+fn test_runs_hooks() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    let hooks_dir = migrate_dir.join("hooks");
+    fs::create_dir_all(&hooks_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    fs::write(
+        migrate_dir.join("1711200001-create-posts.sql"),
+        "-- up\ncreate table posts (id int);\n-- down\ndrop table posts;\n",
+    )?;
+    fs::write(
+        hooks_dir.join("after_each.sql"),
+        "create table if not exists hook_log (name text);\ninsert into hook_log values ('after_each');\n",
+    )?;
+    let log = workdir.join("hooks.log");
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.env(
+        "MIGRATE_HOOK_BEFORE_ALL",
+        format!("echo \"$FLY_HOOK $FLY_DIRECTION\" >> {}", log.display()),
+    );
+    cmd.env(
+        "MIGRATE_HOOK_AFTER_EACH",
+        format!(
+            "echo \"$FLY_HOOK $FLY_MIGRATION_NAME\" >> {}",
+            log.display()
+        ),
+    );
+    cmd.current_dir(&workdir);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("running hooks/after_each.sql"));
+
+    assert_eq!(
+        fs::read_to_string(&log)?,
+        "before_all up
+after_each 1711200000-create-users.sql
+after_each 1711200001-create-posts.sql
+"
+    );
+    let count: i64 = database
+        .connect()?
+        .query_one("select count(*) from hook_log", &[])?
+        .get(0);
+    assert_eq!(count, 2);
+
+    // A failing before hook stops the rollback.
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("down");
+    cmd.env("MIGRATE_HOOK_BEFORE_EACH", "exit 3");
+    cmd.current_dir(&workdir);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "before_each hook failed: command exited with exit status: 3",
+    ));
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("status");
+    cmd.current_dir(&workdir);
+    cmd.assert().success().stdout(predicate::str::contains(
+        "1711200001-create-posts.sql [applied]",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_renders_template_migrations() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- fly:template
+-- up
+create table ${table_name} (id int);
+comment on table ${table_name} is '$${not a placeholder}';
+-- down
+drop table ${table_name};
+",
+    )?;
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.current_dir(&workdir);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "undefined template variable table_name in 1711200000-create-users.sql at line 3",
+    ));
+
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.env("MIGRATE_VAR_TABLE_NAME", "accounts");
+    cmd.current_dir(&workdir);
+    cmd.assert().success();
+
+    let comment: String = database
+        .connect()?
+        .query_one("select obj_description('accounts'::regclass)", &[])?
+        .get(0);
+    assert_eq!(comment, "${not a placeholder}");
+
+    // The rendered sql is what's stored, so the same variables mean no change.
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("status");
+    cmd.env("MIGRATE_VAR_TABLE_NAME", "accounts");
+    cmd.current_dir(&workdir);
+    cmd.assert().success().stdout(predicate::str::contains(
+        "1711200000-create-users.sql [applied]",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_migrates_tenant_schemas() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+    database.connect()?.batch_execute(
+        "create schema tenant_a; create schema tenant_b; create domain email as text;",
+    )?;
+
+    // Types in public can be used unqualified.
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- fly:template
+-- up
+create table users (id int, email email);
+comment on table users is '${schema}';
+-- down
+drop table users;
+",
+    )?;
+
+    let fly = |args: &[&str], schemas: &str| -> Result<Assert> {
+        let mut cmd = Command::cargo_bin("fly")?;
+        cmd.args(args);
+        cmd.env("MIGRATE_SCHEMAS", schemas);
+        cmd.current_dir(&workdir);
+        Ok(cmd.assert())
+    };
+
+    // A missing schema fails, but the others are still migrated.
+    fly(&["up"], "tenant_a, missing, tenant_b")?
+        .failure()
+        .stdout(
+            predicate::str::contains(
+                "tenant{schema=tenant_a}: applying 1711200000-create-users.sql",
+            )
+            .and(predicate::str::contains(
+                "tenant{schema=missing}: schema missing does not exist",
+            ))
+            .and(predicate::str::contains(
+                "tenant{schema=tenant_b}: applying 1711200000-create-users.sql",
+            )),
+        )
+        .stderr(predicate::str::contains(
+            "failed in 1 of 3 targets: missing",
+        ));
+
+    let mut client = database.connect()?;
+    for schema in ["tenant_a", "tenant_b"] {
+        let comment: String = client
+            .query_one(
+                &format!("select obj_description('{}.users'::regclass)", schema),
+                &[],
+            )?
+            .get(0);
+        assert_eq!(comment, schema);
+    }
+
+    fly(&["status"], "tenant_a,tenant_b")?
+        .success()
+        .stdout(predicate::str::contains(
+            "tenant{schema=tenant_b}: 1711200000-create-users.sql [applied]",
+        ));
+
+    // With --fail-fast, tenants after the failing one are left alone.
+    fly(&["down", "--fail-fast"], "missing,tenant_a")?
+        .failure()
+        .stderr(predicate::str::contains(
+            "aborting after failure in missing",
+        ));
+    fly(&["status"], "tenant_a")?
+        .success()
+        .stdout(predicate::str::contains(
+            "1711200000-create-users.sql [applied]",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_migrates_multiple_databases() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let one = common::TestDatabase::new()?;
+    let two = common::TestDatabase::new()?;
+    fs::write(
+        workdir.join(".env"),
+        format!("MIGRATE_DIR={}\n", migrate_dir.display()),
+    )?;
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+
+    let fly = |args: &[&str], databases: &[(&str, &str)]| -> Result<Assert> {
+        let databases = databases
+            .iter()
+            .map(|(name, connection_string)| format!("{}={}", name, connection_string))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut cmd = Command::cargo_bin("fly")?;
+        cmd.args(args);
+        cmd.env("MIGRATE_DATABASES", databases);
+        cmd.current_dir(&workdir);
+        Ok(cmd.assert())
+    };
+    let one = ("one", one.connection_string());
+    let two = ("two", two.connection_string());
+    let unreachable = (
+        "unreachable",
+        "postgresql://nobody@127.0.0.1:1/none".to_string(),
+    );
+
+    // An unreachable database fails, but the others are still migrated.
+    fly(
+        &["up", "--jobs", "2"],
+        &[
+            (one.0, &one.1),
+            (unreachable.0, &unreachable.1),
+            (two.0, &two.1),
+        ],
+    )?
+    .failure()
+    .stdout(
+        predicate::str::contains("database{name=one}: applying 1711200000-create-users.sql").and(
+            predicate::str::contains("database{name=two}: applying 1711200000-create-users.sql"),
+        ),
+    )
+    .stderr(predicate::str::contains(
+        "failed in 1 of 3 targets: unreachable",
+    ));
+
+    fs::write(
+        migrate_dir.join("1711200001-create-posts.sql"),
+        "-- up\ncreate table posts (id int);\n-- down\ndrop table posts;\n",
+    )?;
+    fly(&["up"], &[(one.0, &one.1)])?.success();
+
+    fly(&["status"], &[(one.0, &one.1), (two.0, &two.1)])?
+        .success()
+        .stdout(
+            predicate::str::is_match(
+                r"migration +one +two\n.*1711200000-create-users.sql +applied +applied\n.*1711200001-create-posts.sql +applied +pending",
+            )
+            .unwrap(),
+        );
+
+    Ok(())
+}
+
+#[test]
+fn test_imports_other_tools_tables() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    let fly = |args: &[&str]| -> Result<Assert> {
+        let mut cmd = Command::cargo_bin("fly")?;
+        cmd.args(args);
+        cmd.current_dir(&workdir);
+        Ok(cmd.assert())
+    };
+
+    fly(&["import", "--from", "rails"])?
+        .failure()
+        .stderr(predicate::str::contains(
+            "table schema_migrations not found, nothing to import",
+        ));
+
+    for (name, table) in [
+        ("20240323120000_create_users.sql", "users"),
+        ("20240323120100_create_posts.sql", "posts"),
+    ] {
+        fs::write(
+            migrate_dir.join(name),
+            format!("-- up\ncreate table {table} (id int);\n-- down\ndrop table {table};\n"),
+        )?;
+    }
+    let mut client = database.connect()?;
+    client.batch_execute(
+        "create table users (id int);
+         create table schema_migrations (version varchar primary key);
+         insert into schema_migrations values ('20240323120000'), ('20240101000000');",
+    )?;
+
+    fly(&["import", "--from", "rails"])?.success().stdout(
+        predicate::str::contains("importing 20240323120000_create_users.sql from rails").and(
+            predicate::str::contains(
+                "rails applied version 20240101000000, which has no migration",
+            ),
+        ),
+    );
+    fly(&["status"])?.success().stdout(
+        predicate::str::contains("20240323120000_create_users.sql [applied]").and(
+            predicate::str::contains("20240323120100_create_posts.sql [pending]"),
+        ),
+    );
+    fly(&["up"])?.success().stdout(predicate::str::contains(
+        "applying 20240323120100_create_posts.sql",
+    ));
+
+    // Importing again finds nothing new.
+    fly(&["import", "--from", "rails"])?
+        .success()
+        .stdout(predicate::str::contains("no pending migrations to import"));
+
+    Ok(())
+}
+
+#[test]
+fn test_converts_other_tools_migrations() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    let flyway_dir = workdir.join("flyway");
+    fs::create_dir(&migrate_dir)?;
+    fs::create_dir(&flyway_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    fs::write(
+        flyway_dir.join("V1__create_users.sql"),
+        "create table users (id int, active boolean);\n",
+    )?;
+    fs::write(
+        flyway_dir.join("U1__create_users.sql"),
+        "drop table users;\n",
+    )?;
+    fs::write(
+        flyway_dir.join("V2__add_name.sql"),
+        "alter table users add column name text;\n",
+    )?;
+    fs::write(
+        flyway_dir.join("R__active_users.sql"),
+        "create or replace view active_users as select id from users where active;\n",
+    )?;
+
+    let fly = |args: &[&str]| -> Result<Assert> {
+        let mut cmd = Command::cargo_bin("fly")?;
+        cmd.args(args);
+        cmd.current_dir(&workdir);
+        Ok(cmd.assert())
+    };
+
+    fly(&["convert", "--from", "flyway", "flyway"])?
+        .success()
+        .stdout(
+            predicate::str::contains("0000000001_create_users.sql").and(predicate::str::contains(
+                "no down sql found for 1 migration(s), written with an empty -- down section: 0000000002_add_name.sql",
+            )),
+        );
+    assert_eq!(
+        fs::read_to_string(migrate_dir.join("0000000001_create_users.sql"))?,
+        "-- up\ncreate table users (id int, active boolean);\n\n-- down\ndrop table users;\n"
+    );
+
+    fly(&["up"])?.success().stdout(
+        predicate::str::contains("applying 0000000002_add_name.sql")
+            .and(predicate::str::contains("applying active_users.sql")),
+    );
+
+    // Converting again would overwrite the converted files.
+    fly(&["convert", "--from", "flyway", "flyway"])?
+        .failure()
+        .stderr(predicate::str::contains("active_users.sql already exists"));
+
+    Ok(())
+}
+
+#[test]
+fn test_protected_targets() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+
+    let fly = |args: &[&str], env: (&str, &str)| -> Result<Assert> {
+        let mut cmd = Command::cargo_bin("fly")?;
+        cmd.args(args);
+        cmd.env(env.0, env.1);
+        cmd.current_dir(&workdir);
+        // Not a terminal, so fly can't ask for confirmation.
+        cmd.stdin(Stdio::null());
+        Ok(cmd.assert())
+    };
+    let protected = ("MIGRATE_PROTECTED", "true");
+    let pattern = ("MIGRATE_PROTECTED_DATABASES", "staging, fly-test-*");
+
+    fly(&["up"], protected)?.success();
+
+    fly(&["down"], protected)?
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "{} is protected, pass --yes to roll back without confirming",
+            database.database
+        )));
+    fly(&["status"], protected)?
+        .success()
+        .stdout(predicate::str::contains(
+            "1711200000-create-users.sql [applied]",
+        ));
+
+    fly(&["redo"], pattern)?
+        .failure()
+        .stdout(predicate::str::contains(format!(
+            "{} is protected. About to redo:",
+            database.database
+        )))
+        .stderr(predicate::str::contains("pass --yes to redo"));
+    fly(&["redo", "--yes"], pattern)?.success().stdout(
+        predicate::str::contains("reverting 1711200000-create-users.sql").and(
+            predicate::str::contains("applying 1711200000-create-users.sql"),
+        ),
+    );
+
+    fly(&["down", "--yes"], protected)?
+        .success()
+        .stdout(predicate::str::contains(
+            "reverting 1711200000-create-users.sql",
+        ));
+
+    // Baselining needs confirming too.
+    fly(&["baseline", "1711200000-create-users.sql"], protected)?
+        .failure()
+        .stderr(predicate::str::contains("pass --yes to baseline"));
+
+    // Tenant schemas are confirmed together, once per database.
+    database
+        .connect()?
+        .batch_execute("create schema tenant_a; create schema tenant_b;")?;
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("up");
+    cmd.env("MIGRATE_SCHEMAS", "tenant_a,tenant_b");
+    cmd.current_dir(&workdir);
+    cmd.assert().success();
+    let mut cmd = Command::cargo_bin("fly")?;
+    cmd.arg("down");
+    cmd.env("MIGRATE_SCHEMAS", "tenant_a,tenant_b");
+    cmd.env(protected.0, protected.1);
+    cmd.current_dir(&workdir);
+    cmd.stdin(Stdio::null());
+    let output = cmd.output()?;
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("tenant_a: 1711200000-create-users.sql"));
+    assert!(stdout.contains("tenant_b: 1711200000-create-users.sql"));
+    assert!(!stdout.contains("reverting"));
+    assert_eq!(stdout.matches("pass --yes to roll back").count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_shows_a_migration() -> Result<()> {
+    let workdir = tempdir()?.into_path();
+    let migrate_dir = workdir.join("migrations");
+    fs::create_dir(&migrate_dir)?;
+    let database = common::TestDatabase::new()?;
+    database.write_env(&workdir, &migrate_dir)?;
+
+    let fly = |args: &[&str]| -> Result<Assert> {
+        let mut cmd = Command::cargo_bin("fly")?;
+        cmd.args(args);
+        cmd.current_dir(&workdir);
+        Ok(cmd.assert())
+    };
+
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\ncomment on table users is 'people';\n-- down\ndrop table users;\n",
+    )?;
+    fly(&["show", "1711200000-create-users.sql"])?
+        .success()
+        .stdout(predicate::str::contains(
+            "1711200000-create-users.sql [pending]
+kind: versioned
+
+-- up
+create table users (id int);
+comment on table users is 'people';
+
+-- down
+drop table users;
+",
+        ));
+
+    fly(&["up"])?.success();
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id bigint);\ncomment on table users is 'people';\n-- down\ndrop table users;\n",
+    )?;
+    fly(&["show", "1711200000-create-users.sql"])?
+        .success()
+        .stdout(
+            predicate::str::contains("1711200000-create-users.sql ** CHANGED **")
+                .and(predicate::str::is_match(r"applied \S+ by \S+@\S+ with fly").unwrap())
+                .and(predicate::str::contains(
+                    "sql in the file:
+
+-- up
+create table users (id bigint);
+comment on table users is 'people';
+
+-- down
+drop table users;
+
+sql stored in the database:
+
+-- up
+create table users (id int);
+",
+                ))
+                .and(predicate::str::contains(
+                    "--- database (up)
++++ file (up)
+@@ -1,2 +1,2 @@
+-create table users (id int);
++create table users (id bigint);
+ comment on table users is 'people';
+",
+                ))
+                .and(predicate::str::contains("down sql is unchanged"))
+                .and(predicate::str::contains("(down)").not()),
+        );
+
+    fly(&["show", "1711200009-missing.sql"])?
+        .failure()
+        .stderr(predicate::str::contains(
+            "couldn't find migration 1711200009-missing.sql",
+        ));
+
+    Ok(())
+}
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 use std::env;
+use std::fs;
+use std::path::Path;
 use std::process::Command;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
@@ -54,6 +56,46 @@ impl TestDatabase {
     }
 }
 
+impl TestDatabase {
+    /// Writes a `.env` file to `workdir` pointing fly at this database.
+    pub fn write_env(&self, workdir: &Path, migrate_dir: &Path) -> Result<()> {
+        fs::write(
+            workdir.join(".env"),
+            format!(
+                r#"
+MIGRATE_DIR={migrate_dir}
+PG_HOST={host}
+PG_USER={user}
+PG_PORT={port}
+PG_DB={database}
+"#,
+                migrate_dir = migrate_dir.to_string_lossy(),
+                host = self.host,
+                user = self.user,
+                port = self.port,
+                database = self.database
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+impl TestDatabase {
+    /// Opens a separate connection to this database, e.g. to hold locks
+    /// while fly runs.
+    pub fn connect(&self) -> Result<postgres::Client> {
+        let client = postgres::Client::connect(&self.connection_string(), postgres::NoTls)?;
+        Ok(client)
+    }
+
+    pub fn connection_string(&self) -> String {
+        format!(
+            "postgresql://{}@{}:{}/{}",
+            self.user, self.host, self.port, self.database
+        )
+    }
+}
+
 impl Drop for TestDatabase {
     fn drop(&mut self) {
         eprintln!("Dropping {}", &self.database);
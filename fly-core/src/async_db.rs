@@ -0,0 +1,307 @@
+//! An async [`Db`](crate::db::Db) on tokio-postgres, for use inside a tokio
+//! runtime, where the blocking client panics. Behaves exactly like `Db`:
+//! the same tables, transactions, timeouts and lock retries.
+
+use crate::config::{Config, RetryPolicy, Tenants};
+use crate::db::{
+    bookkeeping_error, commit_error, expect_applied, parse_history_entry,
+    parse_migration_with_meta, statement_error, timeout_settings, transaction_error, Change,
+    Record, Retries, CREATE_HISTORY_TABLE, CREATE_MIGRATIONS_TABLE, CURRENT_SETTING, SCHEMA_EXISTS,
+    SELECT_HISTORY, SELECT_MIGRATIONS, SET_CONFIG_LOCAL, SET_SEARCH_PATH, TABLE_EXISTS,
+    UPGRADE_MIGRATIONS_TABLE,
+};
+use crate::error::{Error, Result};
+use crate::history::HistoryEntry;
+use crate::import::Tool;
+use crate::migration::{Migration, MigrationWithMeta, Section, Timeouts};
+use crate::sql;
+use std::time::Instant;
+use tokio_postgres::{Client, GenericClient, NoTls, Transaction};
+use tracing::{debug, error};
+
+/// Like [`Connection`](crate::db::Connection), for tokio-postgres: a
+/// [`Client`], a [`Transaction`], a mutable reference to either, or with the
+/// `deadpool` feature a pooled connection.
+pub trait AsyncConnection {
+    type Client: GenericClient + Send + Sync;
+
+    fn client(&mut self) -> &mut Self::Client;
+}
+
+impl AsyncConnection for Client {
+    type Client = Client;
+
+    fn client(&mut self) -> &mut Client {
+        self
+    }
+}
+
+impl<'a> AsyncConnection for Transaction<'a> {
+    type Client = Transaction<'a>;
+
+    fn client(&mut self) -> &mut Transaction<'a> {
+        self
+    }
+}
+
+impl<C: AsyncConnection + ?Sized> AsyncConnection for &mut C {
+    type Client = C::Client;
+
+    fn client(&mut self) -> &mut C::Client {
+        (**self).client()
+    }
+}
+
+#[cfg(feature = "deadpool")]
+impl AsyncConnection for deadpool_postgres::Object {
+    type Client = Client;
+
+    fn client(&mut self) -> &mut Client {
+        self
+    }
+}
+
+pub struct AsyncDb<C: AsyncConnection = Client> {
+    client: C,
+    timeouts: Timeouts,
+    lock_retry: RetryPolicy,
+}
+
+impl AsyncDb {
+    /// Connects without TLS, driving the connection on a spawned task.
+    pub async fn connect(config: &Config) -> Result<AsyncDb> {
+        let (client, connection) = tokio_postgres::connect(&config.connection_string, NoTls)
+            .await
+            .map_err(Error::Connection)?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                error!("database connection error: {}", e);
+            }
+        });
+        Ok(AsyncDb::from_client(client, config))
+    }
+}
+
+impl<C: AsyncConnection> AsyncDb<C> {
+    /// Wraps an existing connection. `config.connection_string` is unused.
+    pub fn from_client(client: C, config: &Config) -> AsyncDb<C> {
+        AsyncDb {
+            client,
+            timeouts: config.timeouts,
+            lock_retry: config.lock_retry,
+        }
+    }
+
+    /// Gives back the connection.
+    pub fn into_inner(self) -> C {
+        self.client
+    }
+
+    pub async fn create_migrations_table(&mut self) -> Result<()> {
+        let client = self.client.client();
+        client.batch_execute(CREATE_MIGRATIONS_TABLE).await?;
+        client.batch_execute(UPGRADE_MIGRATIONS_TABLE).await?;
+        client.batch_execute(CREATE_HISTORY_TABLE).await?;
+        Ok(())
+    }
+
+    /// The tenant schemas to migrate, in order.
+    pub async fn schemas(&mut self, tenants: &Tenants) -> Result<Vec<String>> {
+        match tenants {
+            Tenants::Schemas(schemas) => Ok(schemas.clone()),
+            Tenants::Query(query) => {
+                let rows = self.client.client().query(query, &[]).await?;
+                rows.iter().map(|row| Ok(row.try_get(0)?)).collect()
+            }
+        }
+    }
+
+    /// Points the connection at a tenant schema, so that migrations, their
+    /// bookkeeping tables and unqualified names all go there. Names that
+    /// aren't found in it are still looked up in `public`.
+    pub async fn set_schema(&mut self, schema: &str) -> Result<()> {
+        let client = self.client.client();
+        let exists = client.query_opt(SCHEMA_EXISTS, &[&schema]).await?.is_some();
+        if !exists {
+            return Err(Error::SchemaNotFound {
+                schema: schema.to_string(),
+            });
+        }
+        client.execute(SET_SEARCH_PATH, &[&schema]).await?;
+        Ok(())
+    }
+
+    pub async fn list(&mut self) -> Result<Vec<MigrationWithMeta>> {
+        let rows = self.client.client().query(SELECT_MIGRATIONS, &[]).await?;
+        rows.iter().map(parse_migration_with_meta).collect()
+    }
+
+    /// Lists recorded history events, oldest first, optionally only those of
+    /// one migration.
+    pub async fn history(&mut self, name: Option<&str>) -> Result<Vec<HistoryEntry>> {
+        let rows = self.client.client().query(SELECT_HISTORY, &[&name]).await?;
+        rows.iter().map(parse_history_entry).collect()
+    }
+
+    /// The versions another migration tool recorded as applied, in its own
+    /// table.
+    pub async fn imported_versions(&mut self, tool: Tool) -> Result<Vec<String>> {
+        let client = self.client.client();
+        if client
+            .query_opt(TABLE_EXISTS, &[&tool.table()])
+            .await?
+            .is_none()
+        {
+            return Err(Error::ImportTableNotFound {
+                table: tool.table().to_string(),
+            });
+        }
+        let rows = client.query(tool.applied_versions_query(), &[]).await?;
+        rows.iter().map(|row| Ok(row.try_get(0)?)).collect()
+    }
+
+    pub async fn run(&mut self, migration: &Migration) -> Result<MigrationWithMeta> {
+        debug!("inserting migration {:?}", migration);
+        let applied = self.in_transaction(migration, Change::Apply).await?;
+        expect_applied(migration, applied)
+    }
+
+    /// Records a migration as applied without running it, e.g. for a
+    /// database whose schema was created some other way.
+    pub async fn baseline(&mut self, migration: &Migration) -> Result<MigrationWithMeta> {
+        debug!("baselining migration {:?}", migration);
+        let applied = self.in_transaction(migration, Change::Baseline).await?;
+        expect_applied(migration, applied)
+    }
+
+    /// Re-runs a repeatable migration and updates its stored sql.
+    pub async fn reapply(&mut self, migration: &Migration) -> Result<MigrationWithMeta> {
+        debug!("reapplying migration {:?}", migration);
+        let applied = self.in_transaction(migration, Change::Reapply).await?;
+        expect_applied(migration, applied)
+    }
+
+    /// Replaces the stored sql of an applied migration with `migration`'s,
+    /// without running anything, so that it's no longer reported as changed.
+    pub async fn repair(&mut self, migration: &Migration) -> Result<MigrationWithMeta> {
+        debug!("repairing migration {:?}", migration);
+        let applied = self.in_transaction(migration, Change::Repair).await?;
+        expect_applied(migration, applied)
+    }
+
+    pub async fn rollback_migration(&mut self, migration: &Migration) -> Result<()> {
+        debug!("rolling back migration {:?}", migration);
+        self.in_transaction(migration, Change::Rollback).await?;
+        Ok(())
+    }
+
+    /// Runs a sql hook's statements, without any bookkeeping.
+    pub async fn run_hook(&mut self, hook: &Migration) -> Result<()> {
+        debug!("running hook {:?}", hook);
+        self.in_transaction(hook, Change::Hook).await?;
+        Ok(())
+    }
+
+    // As in `Db`: makes a change in a transaction with the migration's
+    // timeouts set, retrying lock timeouts as the policy allows.
+    async fn in_transaction(
+        &mut self,
+        migration: &Migration,
+        change: Change,
+    ) -> Result<Option<MigrationWithMeta>> {
+        let timeouts = migration.timeouts.or(&self.timeouts);
+        let mut retries = Retries::new(migration, self.lock_retry);
+        loop {
+            let result = async {
+                let transaction = self.client.client().transaction().await?;
+                let replaced = set_timeouts(&transaction, &timeouts).await?;
+                let applied = make_change(&transaction, migration, change).await?;
+                restore_settings(&transaction, &replaced).await?;
+                transaction
+                    .commit()
+                    .await
+                    .map_err(|e| commit_error(migration, e))?;
+                Ok(applied)
+            }
+            .await
+            .map_err(|e| transaction_error(migration, e));
+            match result {
+                Err(e) => tokio::time::sleep(retries.next(e)?).await,
+                applied => return applied,
+            }
+        }
+    }
+}
+
+async fn set_timeouts(
+    transaction: &Transaction<'_>,
+    timeouts: &Timeouts,
+) -> Result<Vec<(&'static str, String)>> {
+    let mut replaced = vec![];
+    for (setting, value) in timeout_settings(timeouts) {
+        let current = transaction.query_one(CURRENT_SETTING, &[&setting]).await?;
+        replaced.push((setting, current.try_get(0)?));
+        transaction
+            .execute(SET_CONFIG_LOCAL, &[&setting, &value])
+            .await?;
+    }
+    Ok(replaced)
+}
+
+async fn restore_settings(
+    transaction: &Transaction<'_>,
+    settings: &[(&'static str, String)],
+) -> Result<()> {
+    for (setting, value) in settings {
+        transaction
+            .execute(SET_CONFIG_LOCAL, &[setting, value])
+            .await?;
+    }
+    Ok(())
+}
+
+// Runs a change's section, if it has one, then records it.
+async fn make_change(
+    transaction: &Transaction<'_>,
+    migration: &Migration,
+    change: Change,
+) -> Result<Option<MigrationWithMeta>> {
+    let execution_ms = match change.section() {
+        Some(section) => Some(execute(transaction, migration, section).await?),
+        None => None,
+    };
+    let record = Record::new(migration, change, execution_ms);
+    let mut applied = None;
+    if let Some((statement, params)) = record.migration_statement() {
+        let rows = transaction
+            .query(statement, &params)
+            .await
+            .map_err(|e| bookkeeping_error(migration, e))?;
+        applied = record.applied(&rows)?;
+    }
+    if let Some((statement, params)) = record.history_statement() {
+        transaction
+            .execute(statement, &params)
+            .await
+            .map_err(|e| bookkeeping_error(migration, e))?;
+    }
+    Ok(applied)
+}
+
+// Runs one section of a migration a statement at a time, returning how long
+// it took in milliseconds.
+async fn execute(
+    transaction: &Transaction<'_>,
+    migration: &Migration,
+    section: Section,
+) -> Result<i64> {
+    let start = Instant::now();
+    for (i, statement) in sql::statements(migration.sql(section)).iter().enumerate() {
+        debug!("executing statement {}: {}", i + 1, statement.sql);
+        transaction
+            .batch_execute(statement.sql)
+            .await
+            .map_err(|e| statement_error(migration, section, i + 1, statement, e))?;
+    }
+    Ok(start.elapsed().as_millis() as i64)
+}
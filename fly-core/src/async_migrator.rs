@@ -0,0 +1,302 @@
+//! An async [`Migrator`](crate::migrator::Migrator), for services running
+//! on tokio:
+//!
+//! ```no_run
+//! use fly::async_migrator::AsyncMigrator;
+//!
+//! # async fn migrate() -> fly::error::Result<()> {
+//! let mut migrator = AsyncMigrator::builder("db/migrate")
+//!     .connection_string("postgresql://app@localhost/app")
+//!     .build()
+//!     .await?;
+//! migrator.up().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::async_db::{AsyncConnection, AsyncDb};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::history::HistoryEntry;
+use crate::hooks::{HookContext, HookPoint, Hooks};
+use crate::import::{to_import, ImportReport, Tool};
+use crate::migration::{Migration, Section};
+use crate::migrator::{
+    is_changed, names, to_apply, to_reapply, to_redo, to_revert, DownOptions, DownReport,
+    MigratorBuilder, RedoReport, UpReport,
+};
+use crate::planner::{definitions, get_all_migration_state_impl, ApplicationState};
+use crate::repair::{to_baseline, to_repair};
+use crate::source::{Directory, MigrationSource};
+use std::path::Path;
+use tokio_postgres::Client;
+use tracing::{debug, info};
+
+/// Plans and runs migrations like [`Migrator`](crate::migrator::Migrator),
+/// on an [`AsyncDb`].
+pub struct AsyncMigrator<C: AsyncConnection = Client> {
+    config: Config,
+    db: AsyncDb<C>,
+    source: Box<dyn MigrationSource + Send + Sync>,
+}
+
+impl MigratorBuilder<Client> {
+    pub async fn build(self) -> Result<AsyncMigrator> {
+        let migrator = match self.client {
+            Some(client) => AsyncMigrator::from_client(client, self.config),
+            None => AsyncMigrator::connect(self.config).await?,
+        };
+        Ok(match self.source {
+            Some(source) => AsyncMigrator { source, ..migrator },
+            None => migrator,
+        })
+    }
+}
+
+impl AsyncMigrator {
+    /// Like [`Migrator::builder`](crate::migrator::Migrator::builder), for
+    /// a tokio-postgres [`Client`]. A `client` passed to the builder must
+    /// already have its connection driven, e.g. on a spawned task.
+    pub fn builder(migrate_dir: impl AsRef<Path>) -> MigratorBuilder<Client> {
+        MigratorBuilder::new(migrate_dir)
+    }
+
+    /// Connects to `config.connection_string`.
+    pub async fn connect(config: Config) -> Result<AsyncMigrator> {
+        let db = AsyncDb::connect(&config).await?;
+        Ok(AsyncMigrator {
+            source: Box::new(Directory::new(&config.migrate_dir)),
+            config,
+            db,
+        })
+    }
+}
+
+impl<C: AsyncConnection> AsyncMigrator<C> {
+    /// Migrates through an existing connection, e.g. a pooled one or a
+    /// transaction to commit along with other changes.
+    pub fn from_client(client: C, config: Config) -> AsyncMigrator<C> {
+        let db = AsyncDb::from_client(client, &config);
+        AsyncMigrator {
+            source: Box::new(Directory::new(&config.migrate_dir)),
+            config,
+            db,
+        }
+    }
+
+    /// Reads migrations from `source` instead of the migrations directory.
+    pub fn with_source(mut self, source: impl MigrationSource + Send + Sync + 'static) -> Self {
+        self.source = Box::new(source);
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The underlying connection, for lower level operations.
+    pub fn db(&mut self) -> &mut AsyncDb<C> {
+        &mut self.db
+    }
+
+    /// The tenant schemas to migrate, if the config has any.
+    pub async fn schemas(&mut self) -> Result<Vec<String>> {
+        match &self.config.tenants {
+            Some(tenants) => self.db.schemas(tenants).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Switches to a tenant schema: later operations use its bookkeeping
+    /// tables, and `${schema}` in templates refers to it.
+    pub async fn set_schema(&mut self, schema: &str) -> Result<()> {
+        self.db.set_schema(schema).await?;
+        self.config = self.config.for_schema(schema);
+        Ok(())
+    }
+
+    /// The state of every migration, in the order they're applied.
+    pub async fn status(&mut self) -> Result<Vec<ApplicationState>> {
+        self.db.create_migrations_table().await?;
+        let definitions = definitions(&self.config, self.source.as_ref())?;
+        let applications = self.db.list().await?;
+        Ok(get_all_migration_state_impl(definitions, applications))
+    }
+
+    /// Applies pending migrations, then re-applies changed repeatable ones.
+    pub async fn up(&mut self) -> Result<UpReport> {
+        let application_state = self.status().await?;
+        let to_run = to_apply(&application_state);
+        let mut report = UpReport::default();
+        if to_run.is_empty() {
+            return Ok(report);
+        }
+        self.run_batch(Section::Up, &to_run, &application_state)
+            .await?;
+        for definition in to_run {
+            if is_changed(&application_state, definition) {
+                report.reapplied.push(definition.name.clone());
+            } else {
+                report.applied.push(definition.name.clone());
+            }
+        }
+        Ok(report)
+    }
+
+    /// Rolls back a single versioned migration.
+    pub async fn down(&mut self, options: &DownOptions) -> Result<DownReport> {
+        if options.recover && options.ignore_changed {
+            return Err(Error::ConflictingRollbackOptions);
+        }
+        let application_state = self.status().await?;
+        let Some(rollback) = to_revert(&application_state, options)? else {
+            return Ok(DownReport::default());
+        };
+        self.run_batch(Section::Down, &[rollback], &application_state)
+            .await?;
+        Ok(DownReport {
+            reverted: Some(rollback.name.clone()),
+        })
+    }
+
+    /// Rolls back a versioned migration like [`down`](Self::down), then
+    /// applies its file again.
+    pub async fn redo(&mut self, options: &DownOptions) -> Result<RedoReport> {
+        if options.recover && options.ignore_changed {
+            return Err(Error::ConflictingRollbackOptions);
+        }
+        let application_state = self.status().await?;
+        let Some(rollback) = to_redo(&application_state, options)? else {
+            return Ok(RedoReport::default());
+        };
+        let name = rollback.name.clone();
+        self.run_batch(Section::Down, &[rollback], &application_state)
+            .await?;
+        let application_state = self.status().await?;
+        let definition = to_reapply(&application_state, &name)?;
+        self.run_batch(Section::Up, &[definition], &application_state)
+            .await?;
+        Ok(RedoReport { redone: Some(name) })
+    }
+
+    /// The migration [`down`](Self::down) would roll back, without rolling
+    /// it back.
+    pub async fn plan_down(&mut self, options: &DownOptions) -> Result<Option<String>> {
+        if options.recover && options.ignore_changed {
+            return Err(Error::ConflictingRollbackOptions);
+        }
+        let application_state = self.status().await?;
+        Ok(to_revert(&application_state, options)?.map(|m| m.name.clone()))
+    }
+
+    /// The migrations [`repair`](Self::repair) would repair.
+    pub async fn plan_repair(&mut self, name: Option<&str>) -> Result<Vec<String>> {
+        let application_state = self.status().await?;
+        Ok(names(to_repair(&application_state, name)?))
+    }
+
+    /// The migrations [`baseline`](Self::baseline) would mark as applied.
+    pub async fn plan_baseline(&mut self, name: &str) -> Result<Vec<String>> {
+        let application_state = self.status().await?;
+        Ok(names(to_baseline(&application_state, name)?))
+    }
+
+    /// Updates the stored sql of changed migrations (or only `name`) to
+    /// match their files, without running anything. Returns the names of
+    /// the migrations repaired.
+    pub async fn repair(&mut self, name: Option<&str>) -> Result<Vec<String>> {
+        let application_state = self.status().await?;
+        let mut repaired = Vec::new();
+        for definition in to_repair(&application_state, name)? {
+            info!("repairing {}", definition.name);
+            self.db.repair(definition).await?;
+            repaired.push(definition.name.clone());
+        }
+        Ok(repaired)
+    }
+
+    /// Marks pending versioned migrations up to and including `name` as
+    /// applied, without running them. Returns their names.
+    pub async fn baseline(&mut self, name: &str) -> Result<Vec<String>> {
+        let application_state = self.status().await?;
+        let mut baselined = Vec::new();
+        for definition in to_baseline(&application_state, name)? {
+            info!("baselining {}", definition.name);
+            self.db.baseline(definition).await?;
+            baselined.push(definition.name.clone());
+        }
+        Ok(baselined)
+    }
+
+    /// Marks the pending migrations that another tool recorded as applied,
+    /// in its own table, as applied in fly's.
+    pub async fn import(&mut self, tool: Tool) -> Result<ImportReport> {
+        let versions = self.db.imported_versions(tool).await?;
+        let application_state = self.status().await?;
+        let (to_import, unmatched) = to_import(&application_state, tool, &versions);
+        let mut imported = Vec::new();
+        for definition in to_import {
+            info!("importing {} from {}", definition.name, tool);
+            self.db.baseline(definition).await?;
+            imported.push(definition.name.clone());
+        }
+        Ok(ImportReport {
+            imported,
+            unmatched,
+        })
+    }
+
+    /// Recorded history events, oldest first, optionally only those of one
+    /// migration.
+    pub async fn history(&mut self, name: Option<&str>) -> Result<Vec<HistoryEntry>> {
+        self.db.create_migrations_table().await?;
+        self.db.history(name).await
+    }
+
+    // Applies, re-applies or rolls back each migration, surrounded by the
+    // configured hooks. Unlike `Migrator::run_batch` this doesn't take a
+    // closure, and hooks get their context by value, so that the returned
+    // futures are `Send`.
+    async fn run_batch(
+        &mut self,
+        section: Section,
+        migrations: &[&Migration],
+        application_state: &[ApplicationState],
+    ) -> Result<()> {
+        let hooks = Hooks::load(&self.config)?;
+        let db = &mut self.db;
+        let context = HookContext {
+            section,
+            migrations,
+            migration: None,
+        };
+        hooks.run_async(HookPoint::BeforeAll, db, context).await?;
+        for migration in migrations {
+            let context = HookContext {
+                migration: Some(migration),
+                ..context
+            };
+            hooks.run_async(HookPoint::BeforeEach, db, context).await?;
+            match section {
+                Section::Up => {
+                    debug!("{}", migration.up_sql);
+                    if is_changed(application_state, migration) {
+                        info!("reapplying {}", migration.name);
+                        db.reapply(migration).await?;
+                    } else {
+                        info!("applying {}", migration.name);
+                        db.run(migration).await?;
+                    }
+                }
+                Section::Down => {
+                    debug!("{}", migration.down_sql);
+                    info!("reverting {}", migration.name);
+                    db.rollback_migration(migration).await?;
+                }
+            }
+            hooks.run_async(HookPoint::AfterEach, db, context).await?;
+        }
+        hooks.run_async(HookPoint::AfterAll, db, context).await?;
+        Ok(())
+    }
+}
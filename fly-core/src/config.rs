@@ -1,14 +1,82 @@
 use crate::error::{Error, Result};
+use crate::hooks::HookPoint;
+use crate::migration::{parse_duration, Timeouts};
+use crate::naming::NamingPolicy;
+use crate::template;
 use std::{
     collections::HashMap,
     env,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Config {
     pub migrate_dir: PathBuf,
     pub connection_string: String,
+    pub naming: NamingPolicy,
+    /// Timeouts for migrations that don't set their own.
+    pub timeouts: Timeouts,
+    pub lock_retry: RetryPolicy,
+    /// Shell commands to run around migrations, from `MIGRATE_HOOK_*`.
+    pub hook_commands: HashMap<HookPoint, String>,
+    /// Values for placeholders in template migrations, from `MIGRATE_VAR_*`.
+    pub template_vars: HashMap<String, String>,
+    /// Tenant schemas to migrate one after another, each with its own
+    /// migrations table. `None` migrates the connection's default schema.
+    pub tenants: Option<Tenants>,
+    /// Databases to migrate one after another, from `MIGRATE_DATABASES`.
+    /// When set, `connection_string` is the first one's.
+    pub databases: Vec<Database>,
+    /// Whether destructive commands must be confirmed, from
+    /// `MIGRATE_PROTECTED`.
+    pub protected: bool,
+    /// Patterns of database names that are protected, from
+    /// `MIGRATE_PROTECTED_DATABASES`. `*` matches any characters and `?`
+    /// any one character.
+    pub protected_databases: Vec<String>,
+}
+
+/// A named database connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Database {
+    pub name: String,
+    pub connection_string: String,
+}
+
+/// Where to find tenant schemas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tenants {
+    /// A fixed list, from `MIGRATE_SCHEMAS`.
+    Schemas(Vec<String>),
+    /// A query returning schema names in its first column, from
+    /// `MIGRATE_SCHEMAS_QUERY`.
+    Query(String),
+}
+
+/// How to retry a migration that failed because its lock timeout fired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times to retry. 0 disables retries.
+    pub retries: u32,
+    /// The delay before the first retry, doubled for each one after.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before retry number `retry`, counted from 0.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(retry))
+    }
 }
 
 impl Config {
@@ -18,17 +86,130 @@ impl Config {
         Config {
             migrate_dir,
             connection_string,
+            naming: NamingPolicy::relaxed(),
+            timeouts: Timeouts::default(),
+            lock_retry: RetryPolicy::default(),
+            hook_commands: HashMap::new(),
+            template_vars: HashMap::new(),
+            tenants: None,
+            databases: Vec::new(),
+            protected: false,
+            protected_databases: Vec::new(),
+        }
+    }
+
+    /// The config to migrate one of several databases with.
+    pub fn for_database(&self, database: &Database) -> Config {
+        Config {
+            connection_string: database.connection_string.clone(),
+            protected: self.protected || self.protects(&database.name),
+            ..self.clone()
         }
     }
 
+    /// The name of the database `connection_string` connects to, which
+    /// postgres defaults to the user's name.
+    pub fn database_name(&self) -> Option<String> {
+        let config = self.connection_string.parse::<postgres::Config>().ok()?;
+        config
+            .get_dbname()
+            .or(config.get_user())
+            .map(|name| name.to_string())
+    }
+
+    /// Whether destructive commands against this database must be
+    /// confirmed: it's marked protected, or its name matches one of
+    /// `protected_databases`.
+    pub fn is_protected(&self) -> bool {
+        self.protected
+            || self
+                .database_name()
+                .is_some_and(|name| self.protects(&name))
+    }
+
+    fn protects(&self, name: &str) -> bool {
+        self.protected_databases
+            .iter()
+            .any(|pattern| glob_match(pattern, name))
+    }
+
+    /// The config to migrate a single tenant schema with: the same, but with
+    /// the `schema` template variable set.
+    pub fn for_schema(&self, schema: &str) -> Config {
+        let mut config = self.clone();
+        config
+            .template_vars
+            .insert("schema".to_string(), schema.to_string());
+        config
+    }
+
     pub fn from_env() -> Result<Self> {
         let env_vars = env::vars().collect::<HashMap<String, String>>();
-        let migrate_dir = get_env("MIGRATE_DIR", &env_vars)?.into();
-        let connection_string = connection_string_from_env(&env_vars)?;
+        let config = Config::from_env_vars(&env_vars)?;
+        let databases = databases_from_env(&env_vars)?;
+        let connection_string = match databases.first() {
+            Some(database) => database.connection_string.clone(),
+            None => connection_string_from_env(&env_vars)?,
+        };
+        Ok(Config {
+            connection_string,
+            databases,
+            ..config
+        })
+    }
+
+    /// The config from the environment, without a database to connect to,
+    /// for commands that only read the migrations directory, like `fly lint`
+    /// and `fly convert`. `connection_string` is empty.
+    pub fn from_env_offline() -> Result<Self> {
+        Config::from_env_vars(&env::vars().collect())
+    }
+
+    // Everything but the databases to connect to.
+    fn from_env_vars(env_vars: &HashMap<String, String>) -> Result<Self> {
+        let migrate_dir = get_env("MIGRATE_DIR", env_vars)?.into();
+        let naming = naming_policy_from_env(env_vars)?;
+        let timeouts = Timeouts {
+            lock_timeout: duration_from_env("MIGRATE_LOCK_TIMEOUT", env_vars)?,
+            statement_timeout: duration_from_env("MIGRATE_STATEMENT_TIMEOUT", env_vars)?,
+        };
+        let mut lock_retry = RetryPolicy::default();
+        if let Ok(retries) = get_env("MIGRATE_LOCK_RETRIES", env_vars) {
+            lock_retry.retries = retries.parse().map_err(|e| Error::BadEnvFormat {
+                name: "MIGRATE_LOCK_RETRIES".to_string(),
+                reason: format!("{}", e),
+            })?;
+        }
+        if let Some(backoff) = duration_from_env("MIGRATE_LOCK_RETRY_BACKOFF", env_vars)? {
+            lock_retry.backoff = backoff;
+        }
+
+        let hook_commands = HookPoint::ALL
+            .into_iter()
+            .filter_map(|point| Some((point, env_vars.get(&point.env_var())?.clone())))
+            .collect();
 
         Ok(Config {
             migrate_dir,
-            connection_string,
+            connection_string: String::new(),
+            naming,
+            timeouts,
+            lock_retry,
+            hook_commands,
+            template_vars: template::vars_from_env(env_vars),
+            tenants: tenants_from_env(env_vars)?,
+            databases: Vec::new(),
+            protected: bool_from_env("MIGRATE_PROTECTED", env_vars)?,
+            protected_databases: env_vars
+                .get("MIGRATE_PROTECTED_DATABASES")
+                .map(|patterns| {
+                    patterns
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|p| !p.is_empty())
+                        .map(|p| p.to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
         })
     }
 }
@@ -41,6 +222,129 @@ fn get_env(key: &str, vars: &HashMap<String, String>) -> Result<String> {
         })
 }
 
+// Whitespace-separated `name=connection_string` pairs.
+fn databases_from_env(env_vars: &HashMap<String, String>) -> Result<Vec<Database>> {
+    let Some(databases) = env_vars.get("MIGRATE_DATABASES") else {
+        return Ok(Vec::new());
+    };
+    databases
+        .split_whitespace()
+        .map(|entry| match entry.split_once('=') {
+            Some((name, connection_string)) if !name.is_empty() => Ok(Database {
+                name: name.to_string(),
+                connection_string: connection_string.to_string(),
+            }),
+            // The entry isn't quoted, as its connection string may hold a
+            // password.
+            _ => Err(Error::BadEnvFormat {
+                name: "MIGRATE_DATABASES".to_string(),
+                reason: "expected name=connection_string pairs".to_string(),
+            }),
+        })
+        .collect()
+}
+
+fn tenants_from_env(env_vars: &HashMap<String, String>) -> Result<Option<Tenants>> {
+    match (
+        env_vars.get("MIGRATE_SCHEMAS"),
+        env_vars.get("MIGRATE_SCHEMAS_QUERY"),
+    ) {
+        (None, None) => Ok(None),
+        (Some(schemas), None) => {
+            let schemas = schemas
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>();
+            // An empty list would quietly migrate nothing.
+            if schemas.is_empty() {
+                return Err(Error::BadEnvFormat {
+                    name: "MIGRATE_SCHEMAS".to_string(),
+                    reason: "expected a comma-separated list of schemas".to_string(),
+                });
+            }
+            Ok(Some(Tenants::Schemas(schemas)))
+        }
+        (None, Some(query)) => Ok(Some(Tenants::Query(query.clone()))),
+        (Some(_), Some(_)) => Err(Error::BadEnvFormat {
+            name: "MIGRATE_SCHEMAS_QUERY".to_string(),
+            reason: "MIGRATE_SCHEMAS is set too".to_string(),
+        }),
+    }
+}
+
+fn bool_from_env(key: &str, vars: &HashMap<String, String>) -> Result<bool> {
+    match vars.get(key).map(|s| s.as_str()) {
+        None | Some("false") | Some("0") | Some("") => Ok(false),
+        Some("true") | Some("1") => Ok(true),
+        Some(value) => Err(Error::BadEnvFormat {
+            name: key.to_string(),
+            reason: format!("expected true, false, 1 or 0, found {:?}", value),
+        }),
+    }
+}
+
+// Matches `name` against a pattern where `*` matches any characters and `?`
+// any one character.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // matches[j]: whether the pattern so far matches name[..j].
+    let mut matches = vec![false; name.len() + 1];
+    matches[0] = true;
+    for p in pattern {
+        if p == '*' {
+            for j in 1..=name.len() {
+                matches[j] |= matches[j - 1];
+            }
+        } else {
+            for j in (1..=name.len()).rev() {
+                matches[j] = matches[j - 1] && (p == '?' || p == name[j - 1]);
+            }
+            matches[0] = false;
+        }
+    }
+    matches[name.len()]
+}
+
+fn duration_from_env(key: &str, vars: &HashMap<String, String>) -> Result<Option<Duration>> {
+    match vars.get(key) {
+        None => Ok(None),
+        Some(value) => parse_duration(value)
+            .map(Some)
+            .map_err(|e| Error::BadEnvFormat {
+                name: key.to_owned(),
+                reason: format!("{}", e),
+            }),
+    }
+}
+
+fn naming_policy_from_env(env_vars: &HashMap<String, String>) -> Result<NamingPolicy> {
+    let mut naming = match env_vars.get("MIGRATE_NAMING").map(|s| s.as_str()) {
+        None | Some("relaxed") => NamingPolicy::relaxed(),
+        Some("strict") => NamingPolicy::strict(),
+        Some(value) => {
+            return Err(Error::BadEnvFormat {
+                name: "MIGRATE_NAMING".to_string(),
+                reason: format!("expected strict or relaxed, found {:?}", value),
+            })
+        }
+    };
+    if let Ok(digits) = get_env("MIGRATE_VERSION_DIGITS", env_vars) {
+        naming.min_version_digits = digits.parse().map_err(|e| Error::BadEnvFormat {
+            name: "MIGRATE_VERSION_DIGITS".to_string(),
+            reason: format!("{}", e),
+        })?;
+    }
+    // Only the strict policy restricts characters.
+    if naming.require_version {
+        if let Ok(extra_chars) = get_env("MIGRATE_NAME_CHARS", env_vars) {
+            naming.extra_chars = Some(extra_chars);
+        }
+    }
+    Ok(naming)
+}
+
 fn connection_string_from_env(env_vars: &HashMap<String, String>) -> Result<String> {
     if let Ok(connection_string) = get_env("PG_CONNECTION_STRING", env_vars) {
         Ok(connection_string)
@@ -51,8 +355,9 @@ fn connection_string_from_env(env_vars: &HashMap<String, String>) -> Result<Stri
         let pg_port_str = get_env("PG_PORT", env_vars)?;
         let pg_port = pg_port_str
             .parse::<u16>()
-            .map_err(|_| Error::BadEnvFormat {
+            .map_err(|e| Error::BadEnvFormat {
                 name: "PG_PORT".to_string(),
+                reason: format!("{}", e),
             })?;
         let pg_db = get_env("PG_DB", env_vars)?;
 
@@ -68,3 +373,99 @@ fn connection_string_from_env(env_vars: &HashMap<String, String>) -> Result<Stri
         Ok(connection_string)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("prod", "prod"));
+        assert!(glob_match("prod*", "prod_eu"));
+        assert!(glob_match("*prod*", "app_prod_eu"));
+        assert!(glob_match("shard?", "shard1"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("prod", "prod_eu"));
+        assert!(!glob_match("shard?", "shard10"));
+        assert!(!glob_match("*prod", "production"));
+    }
+
+    #[test]
+    fn test_naming_policy_from_env() {
+        let env = |vars: &[(&str, &str)]| {
+            vars.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+        assert_eq!(
+            naming_policy_from_env(&env(&[])).unwrap(),
+            NamingPolicy::relaxed()
+        );
+        assert_eq!(
+            naming_policy_from_env(&env(&[("MIGRATE_NAMING", "strict")])).unwrap(),
+            NamingPolicy::strict()
+        );
+        let strict = naming_policy_from_env(&env(&[
+            ("MIGRATE_NAMING", "strict"),
+            ("MIGRATE_NAME_CHARS", "_"),
+        ]))
+        .unwrap();
+        assert_eq!(strict.extra_chars.as_deref(), Some("_"));
+        assert!(naming_policy_from_env(&env(&[("MIGRATE_NAMING", "loose")])).is_err());
+    }
+
+    #[test]
+    fn test_bad_env_format_reason() {
+        let env = HashMap::from([("MIGRATE_LOCK_TIMEOUT".to_string(), "5 parsecs".to_string())]);
+        let error = duration_from_env("MIGRATE_LOCK_TIMEOUT", &env)
+            .err()
+            .unwrap();
+        assert!(error.to_string().starts_with(
+            "environment variable MIGRATE_LOCK_TIMEOUT could not be parsed: unknown time unit \"parsecs\""
+        ));
+        let env = HashMap::from([("MIGRATE_PROTECTED".to_string(), "yes".to_string())]);
+        assert_eq!(
+            bool_from_env("MIGRATE_PROTECTED", &env)
+                .err()
+                .unwrap()
+                .to_string(),
+            "environment variable MIGRATE_PROTECTED could not be parsed: expected true, false, 1 or 0, found \"yes\""
+        );
+    }
+
+    #[test]
+    fn test_tenants_from_env() {
+        let env = |name: &str, value: &str| HashMap::from([(name.to_string(), value.to_string())]);
+        assert_eq!(
+            tenants_from_env(&env("MIGRATE_SCHEMAS", "tenant_1, tenant_2,")).unwrap(),
+            Some(Tenants::Schemas(vec![
+                "tenant_1".to_string(),
+                "tenant_2".to_string()
+            ]))
+        );
+        for schemas in ["", " ", " , "] {
+            assert_eq!(
+                tenants_from_env(&env("MIGRATE_SCHEMAS", schemas))
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                "environment variable MIGRATE_SCHEMAS could not be parsed: expected a comma-separated list of schemas"
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_protected() {
+        let mut config = Config::new("migrations", "postgresql://app@localhost/app_prod");
+        assert!(!config.is_protected());
+        config.protected_databases = vec!["staging".to_string(), "*_prod".to_string()];
+        assert!(config.is_protected());
+
+        let shard = config.for_database(&Database {
+            name: "staging".to_string(),
+            connection_string: "postgresql://app@localhost/shard1".to_string(),
+        });
+        assert_eq!(shard.database_name().as_deref(), Some("shard1"));
+        assert!(shard.is_protected());
+    }
+}
@@ -0,0 +1,515 @@
+//! Converting another migration tool's migration files to fly's format, one
+//! `-- up`/`-- down` file per migration.
+
+use crate::error::{Error, Result};
+use crate::file::REPEATABLE_DIR;
+use crate::migration::Section;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+/// A layout of migration files that fly can convert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// golang-migrate's `1_create_users.up.sql` and `1_create_users.down.sql`.
+    GolangMigrate,
+    /// dbmate's `20240323120000_create_users.sql`, with `-- migrate:up` and
+    /// `-- migrate:down` sections.
+    Dbmate,
+    /// sqlx's reversible `1_create_users.up.sql` and `.down.sql`, or simple
+    /// `1_create_users.sql` with no down sql.
+    Sqlx,
+    /// Flyway's `V1__create_users.sql`, with an optional `U1__create_users.sql`
+    /// undo migration, and repeatable `R__views.sql`.
+    Flyway,
+}
+
+impl Format {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::GolangMigrate => "golang-migrate",
+            Format::Dbmate => "dbmate",
+            Format::Sqlx => "sqlx",
+            Format::Flyway => "flyway",
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A migration in fly's format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Converted {
+    /// Where to write the migration, relative to the migrations directory,
+    /// e.g. `0000000001_create_users.sql` or `repeatable/views.sql`.
+    pub path: PathBuf,
+    pub contents: String,
+    /// The files it was converted from.
+    pub sources: Vec<PathBuf>,
+    /// Whether it's a versioned migration the tool had no down sql for, so
+    /// that it was written with an empty `-- down` section.
+    pub empty_down: bool,
+}
+
+// A migration's version, or none for a repeatable one, and description.
+type Key = (Option<String>, String);
+
+// The sections of one migration, as found in the other tool's files.
+#[derive(Default)]
+struct Found {
+    up: Option<String>,
+    down: Option<String>,
+    sources: Vec<PathBuf>,
+}
+
+/// Converts the migrations at the top level of `dir`. Versions are padded
+/// with zeros to `version_digits` digits, so that e.g. `1_create_users`
+/// becomes `0000000001_create_users.sql` and still sorts before version 10.
+/// Files that aren't `.sql` are skipped; `.sql` files whose names don't
+/// follow the format are an error.
+pub fn convert(
+    format: Format,
+    dir: impl AsRef<Path>,
+    version_digits: usize,
+) -> Result<Vec<Converted>> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut found: BTreeMap<Key, Found> = BTreeMap::new();
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !path.is_file() || !name.ends_with(".sql") || name.starts_with('.') {
+            continue;
+        }
+        let contents = std::fs::read_to_string(&path)?;
+        let (key, section) = match format {
+            Format::GolangMigrate => parse_up_down_name(&name, false)?,
+            Format::Sqlx => parse_up_down_name(&name, true)?,
+            Format::Flyway => parse_flyway_name(&name)?,
+            Format::Dbmate => {
+                let (version, description) = split_version(&name, "_", &name)?;
+                ((Some(version), description), None)
+            }
+        };
+        let migration = found.entry(key).or_default();
+        match section {
+            Some(Section::Up) => migration.up = Some(contents),
+            Some(Section::Down) => migration.down = Some(contents),
+            None => {
+                let (up, down) = split_dbmate(&name, &contents)?;
+                migration.up = Some(up);
+                migration.down = Some(down);
+            }
+        }
+        migration.sources.push(path);
+    }
+
+    found
+        .into_iter()
+        .map(|((version, description), migration)| {
+            let Some(up) = migration.up else {
+                return Err(Error::MigrationFileFormatError {
+                    reason: "down migration has no up migration".to_string(),
+                    name: file_name(&migration.sources[0]),
+                    line: None,
+                });
+            };
+            let description = sanitize(&description);
+            let down = migration.down.unwrap_or_default();
+            let empty_down = version.is_some() && down.trim().is_empty();
+            let (path, contents) = match version {
+                Some(version) => (
+                    PathBuf::from(format!(
+                        "{:0>width$}_{}.sql",
+                        version,
+                        description,
+                        width = version_digits
+                    )),
+                    format!("-- up\n{}\n\n-- down\n{}\n", up.trim(), down.trim()),
+                ),
+                None => (
+                    Path::new(REPEATABLE_DIR).join(format!("{}.sql", description)),
+                    up,
+                ),
+            };
+            Ok(Converted {
+                path,
+                contents,
+                sources: migration.sources,
+                empty_down,
+            })
+        })
+        .collect()
+}
+
+/// Writes converted migrations into `migrate_dir`, returning the paths
+/// written. If any of the files already exists nothing is written, so a
+/// clash doesn't leave a half-converted directory.
+pub fn write(converted: &[Converted], migrate_dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let paths = converted
+        .iter()
+        .map(|migration| migrate_dir.as_ref().join(&migration.path))
+        .collect::<Vec<_>>();
+    if let Some(path) = paths.iter().find(|path| path.exists()) {
+        return Err(Error::MigrationFileExists { path: path.clone() });
+    }
+    for (path, migration) in paths.iter().zip(converted) {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, &migration.contents)?;
+    }
+    Ok(paths)
+}
+
+// `1_create_users.up.sql` or `1_create_users.down.sql`, and with `simple`
+// also `1_create_users.sql`, which is only an up migration.
+fn parse_up_down_name(name: &str, simple: bool) -> Result<(Key, Option<Section>)> {
+    let (stem, section) = if let Some(stem) = name.strip_suffix(".up.sql") {
+        (stem, Section::Up)
+    } else if let Some(stem) = name.strip_suffix(".down.sql") {
+        (stem, Section::Down)
+    } else if let Some(stem) = name.strip_suffix(".sql").filter(|_| simple) {
+        (stem, Section::Up)
+    } else {
+        return Err(Error::MigrationFileFormatError {
+            reason: "expected a name ending in .up.sql or .down.sql".to_string(),
+            name: name.to_string(),
+            line: None,
+        });
+    };
+    let (version, description) = split_version(stem, "_", name)?;
+    Ok(((Some(version), description), Some(section)))
+}
+
+// `V1__create_users.sql`, `U1__create_users.sql` or `R__views.sql`.
+fn parse_flyway_name(name: &str) -> Result<(Key, Option<Section>)> {
+    let stem = name.strip_suffix(".sql").unwrap_or(name);
+    if let Some(description) = stem.strip_prefix("R__") {
+        return Ok(((None, description.to_string()), Some(Section::Up)));
+    }
+    let (versioned, section) = if let Some(versioned) = stem.strip_prefix('V') {
+        (versioned, Section::Up)
+    } else if let Some(versioned) = stem.strip_prefix('U') {
+        (versioned, Section::Down)
+    } else {
+        return Err(Error::MigrationFileFormatError {
+            reason: "expected a name starting with V, U or R__".to_string(),
+            name: name.to_string(),
+            line: None,
+        });
+    };
+    let (version, description) = split_version(versioned, "__", name)?;
+    Ok(((Some(version), description), Some(section)))
+}
+
+// Splits `1_create_users` into its numeric version and description. Dotted
+// versions such as flyway's `1.1` have no fly equivalent.
+fn split_version(stem: &str, separator: &str, name: &str) -> Result<(String, String)> {
+    let stem = stem.strip_suffix(".sql").unwrap_or(stem);
+    match stem.split_once(separator) {
+        Some((version, description))
+            if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            Ok((version.to_string(), description.to_string()))
+        }
+        Some((version, _))
+            if version.contains('.') && version.chars().all(|c| c.is_ascii_digit() || c == '.') =>
+        {
+            Err(Error::MigrationFileFormatError {
+                reason: format!(
+                    "dotted version {} has no fly equivalent; rename the migrations to whole-number versions in the same order (e.g. 1, 1.1 and 2 to 100, 110 and 200) and convert again",
+                    version
+                ),
+                name: name.to_string(),
+                line: None,
+            })
+        }
+        _ => Err(Error::MigrationFileFormatError {
+            reason: format!(
+                "expected a numeric version followed by {:?} and a description",
+                separator
+            ),
+            name: name.to_string(),
+            line: None,
+        }),
+    }
+}
+
+// Splits a dbmate file at its `-- migrate:up` and `-- migrate:down` lines.
+// fly always runs a migration in a transaction, so a section marked
+// `transaction:false`, e.g. for `CREATE INDEX CONCURRENTLY`, can't be
+// converted.
+fn split_dbmate(name: &str, contents: &str) -> Result<(String, String)> {
+    let mut up = None;
+    let mut down = None;
+    let mut section = None;
+    for (i, line) in contents.lines().enumerate() {
+        let marker = line.trim().strip_prefix("--").map(str::trim);
+        if let Some(m) = marker.filter(|m| m.starts_with("migrate:")) {
+            if m.split_whitespace()
+                .any(|option| option == "transaction:false")
+            {
+                return Err(Error::MigrationFileFormatError {
+                    reason: "transaction:false sections can't be converted, fly runs every migration in a transaction".to_string(),
+                    name: name.to_string(),
+                    line: Some(i + 1),
+                });
+            }
+        }
+        match marker {
+            Some(m) if m.starts_with("migrate:up") => section = Some(&mut up),
+            Some(m) if m.starts_with("migrate:down") => section = Some(&mut down),
+            _ => {
+                if let Some(sql) = section.as_mut() {
+                    let sql = sql.get_or_insert_with(String::new);
+                    sql.push_str(line);
+                    sql.push('\n');
+                }
+            }
+        }
+    }
+    match up {
+        Some(up) => Ok((up, down.unwrap_or_default())),
+        None => Err(Error::MigrationFileFormatError {
+            reason: "no -- migrate:up section".to_string(),
+            name: name.to_string(),
+            line: None,
+        }),
+    }
+}
+
+// Replaces characters fly doesn't allow in names by default with `_`.
+fn sanitize(description: &str) -> String {
+    description
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn write_files(dir: &Path, files: &[(&str, &str)]) -> Result<()> {
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents)?;
+        }
+        Ok(())
+    }
+
+    fn converted(format: Format, files: &[(&str, &str)]) -> Result<Vec<(String, String)>> {
+        let dir = tempdir()?;
+        write_files(dir.path(), files)?;
+        Ok(convert(format, dir.path(), 10)?
+            .into_iter()
+            .map(|c| (c.path.to_string_lossy().into_owned(), c.contents))
+            .collect())
+    }
+
+    #[test]
+    fn test_golang_migrate() -> Result<()> {
+        let result = converted(
+            Format::GolangMigrate,
+            &[
+                (
+                    "000001_create_users.up.sql",
+                    "create table users (id int);\n",
+                ),
+                ("000001_create_users.down.sql", "drop table users;\n"),
+                ("000002_add_index.up.sql", "create index on users (id);\n"),
+                ("README.md", "not a migration"),
+            ],
+        )?;
+        assert_eq!(
+            result,
+            [
+                (
+                    "0000000001_create_users.sql".to_string(),
+                    "-- up\ncreate table users (id int);\n\n-- down\ndrop table users;\n"
+                        .to_string()
+                ),
+                (
+                    "0000000002_add_index.sql".to_string(),
+                    "-- up\ncreate index on users (id);\n\n-- down\n\n".to_string()
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dbmate() -> Result<()> {
+        let result = converted(
+            Format::Dbmate,
+            &[(
+                "20240323120000_create_users.sql",
+                "-- migrate:up\ncreate table users (id int);\n\n-- migrate:down\ndrop table users;\n",
+            )],
+        )?;
+        assert_eq!(
+            result,
+            [(
+                "20240323120000_create_users.sql".to_string(),
+                "-- up\ncreate table users (id int);\n\n-- down\ndrop table users;\n".to_string()
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlx() -> Result<()> {
+        let result = converted(
+            Format::Sqlx,
+            &[
+                ("1_create_users.up.sql", "create table users (id int);"),
+                ("1_create_users.down.sql", "drop table users;"),
+                ("2_seed.sql", "insert into users values (1);"),
+            ],
+        )?;
+        assert_eq!(
+            result
+                .iter()
+                .map(|(path, _)| path.as_str())
+                .collect::<Vec<_>>(),
+            ["0000000001_create_users.sql", "0000000002_seed.sql"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_flyway() -> Result<()> {
+        let result = converted(
+            Format::Flyway,
+            &[
+                ("V1__create users.sql", "create table users (id int);"),
+                ("U1__create users.sql", "drop table users;"),
+                (
+                    "R__active_users.sql",
+                    "create or replace view active_users as select id from users;\n",
+                ),
+            ],
+        )?;
+        assert_eq!(
+            result,
+            [
+                (
+                    "repeatable/active_users.sql".to_string(),
+                    "create or replace view active_users as select id from users;\n".to_string()
+                ),
+                (
+                    "0000000001_create_users.sql".to_string(),
+                    "-- up\ncreate table users (id int);\n\n-- down\ndrop table users;\n"
+                        .to_string()
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write() -> Result<()> {
+        let dir = tempdir()?;
+        write_files(
+            dir.path(),
+            &[("V1__create_users.sql", "create table users (id int);")],
+        )?;
+        let migrate_dir = tempdir()?;
+        let converted = convert(Format::Flyway, dir.path(), 10)?;
+        assert_eq!(
+            write(&converted, migrate_dir.path())?,
+            [migrate_dir.path().join("0000000001_create_users.sql")]
+        );
+        assert_eq!(
+            write(&converted, migrate_dir.path())
+                .err()
+                .unwrap()
+                .to_string(),
+            format!(
+                "{} already exists",
+                migrate_dir
+                    .path()
+                    .join("0000000001_create_users.sql")
+                    .display()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_down() -> Result<()> {
+        let dir = tempdir()?;
+        write_files(
+            dir.path(),
+            &[
+                ("V1__create_users.sql", "create table users (id int);"),
+                ("U1__create_users.sql", "drop table users;"),
+                ("V2__add_name.sql", "alter table users add name text;"),
+                (
+                    "R__views.sql",
+                    "create view user_ids as select id from users;",
+                ),
+            ],
+        )?;
+        let empty_down = convert(Format::Flyway, dir.path(), 10)?
+            .into_iter()
+            .filter(|c| c.empty_down)
+            .map(|c| c.path)
+            .collect::<Vec<_>>();
+        assert_eq!(empty_down, [PathBuf::from("0000000002_add_name.sql")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_unconvertible_names() {
+        assert_eq!(
+            converted(Format::Flyway, &[("V1.1__add_name.sql", "select 1;")])
+                .err()
+                .unwrap()
+                .to_string(),
+            "bad migration file format in V1.1__add_name.sql: dotted version 1.1 has no fly equivalent; rename the migrations to whole-number versions in the same order (e.g. 1, 1.1 and 2 to 100, 110 and 200) and convert again"
+        );
+        assert_eq!(
+            converted(
+                Format::GolangMigrate,
+                &[("1_create_users.down.sql", "drop table users;")]
+            )
+            .err()
+            .unwrap()
+            .to_string(),
+            "bad migration file format in 1_create_users.down.sql: down migration has no up migration"
+        );
+        assert_eq!(
+            converted(
+                Format::Dbmate,
+                &[(
+                    "20240323120000_add_index.sql",
+                    "-- migrate:up transaction:false\ncreate index concurrently on users (id);\n",
+                )]
+            )
+            .err()
+            .unwrap()
+            .to_string(),
+            "bad migration file format in 20240323120000_add_index.sql at line 1: transaction:false sections can't be converted, fly runs every migration in a transaction"
+        );
+    }
+}
@@ -1,11 +1,17 @@
-use crate::error::Result;
-use crate::migration::{Migration, MigrationMeta};
-use crate::{config::Config, migration::MigrationWithMeta};
-use postgres::{Client, NoTls, Row};
-use std::time::SystemTime;
-use tracing::debug;
-
-static CREATE_MIGRATIONS_TABLE: &str = r#"
+use crate::config::{Config, RetryPolicy, Tenants};
+use crate::error::{Error, Result, StatementFailure};
+use crate::history::{HistoryEntry, HistoryEvent};
+use crate::import::Tool;
+use crate::migration::MigrationWithMeta;
+use crate::migration::{Migration, MigrationKind, MigrationMeta, Section, SourceLines, Timeouts};
+use crate::sql::{self, Statement};
+use postgres::error::{ErrorPosition, SqlState};
+use postgres::types::ToSql;
+use postgres::{Client, GenericClient, NoTls, Row, Transaction};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, warn};
+
+pub(crate) static CREATE_MIGRATIONS_TABLE: &str = r#"
   CREATE TABLE IF NOT EXISTS migrations (
       id SERIAL PRIMARY KEY,
       name TEXT NOT NULL UNIQUE,
@@ -15,23 +21,167 @@ static CREATE_MIGRATIONS_TABLE: &str = r#"
   );
 "#;
 
-pub struct Db {
-    client: Client,
+// Brings a migrations table created by an older version of fly up to date.
+// Each statement must be safe to run repeatedly.
+pub(crate) static UPGRADE_MIGRATIONS_TABLE: &str = r#"
+  ALTER TABLE migrations ADD COLUMN IF NOT EXISTS kind TEXT NOT NULL DEFAULT 'versioned';
+  ALTER TABLE migrations ADD COLUMN IF NOT EXISTS applied_by TEXT;
+  ALTER TABLE migrations ADD COLUMN IF NOT EXISTS applied_host TEXT;
+  ALTER TABLE migrations ADD COLUMN IF NOT EXISTS fly_version TEXT;
+  ALTER TABLE migrations ADD COLUMN IF NOT EXISTS execution_ms BIGINT;
+"#;
+
+// Append-only: rows are never updated or deleted by fly.
+pub(crate) static CREATE_HISTORY_TABLE: &str = r#"
+  CREATE TABLE IF NOT EXISTS migration_history (
+      id SERIAL PRIMARY KEY,
+      name TEXT NOT NULL,
+      event TEXT NOT NULL,
+      checksum TEXT NOT NULL,
+      applied_by TEXT,
+      applied_host TEXT,
+      fly_version TEXT,
+      execution_ms BIGINT,
+      created_at TIMESTAMP NOT NULL DEFAULT NOW()
+  );
+"#;
+
+pub(crate) static SCHEMA_EXISTS: &str = "SELECT 1 FROM pg_namespace WHERE nspname = $1";
+pub(crate) static TABLE_EXISTS: &str = "SELECT 1 FROM information_schema.tables
+     WHERE table_name = $1 AND table_schema = ANY(current_schemas(false))";
+// The tenant schema comes first, so that new tables go there, followed by
+// public, where extensions and shared types are usually installed.
+pub(crate) static SET_SEARCH_PATH: &str =
+    "SELECT set_config('search_path', format('%I, public', $1::text), false)";
+pub(crate) static SELECT_MIGRATIONS: &str = "SELECT * FROM migrations";
+pub(crate) static SELECT_HISTORY: &str =
+    "SELECT * FROM migration_history WHERE $1::TEXT IS NULL OR name = $1 ORDER BY id";
+pub(crate) static DELETE_MIGRATION: &str = "DELETE FROM migrations WHERE name = $1";
+pub(crate) static SET_CONFIG_LOCAL: &str = "SELECT set_config($1, $2, true)";
+pub(crate) static CURRENT_SETTING: &str = "SELECT current_setting($1)";
+pub(crate) static INSERT_MIGRATION: &str =
+    "INSERT INTO migrations (name, up_sql, down_sql, kind, applied_by, applied_host, fly_version, execution_ms)
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *";
+pub(crate) static UPDATE_MIGRATION: &str =
+    "UPDATE migrations SET up_sql = $2, down_sql = $3, kind = $4, created_at = NOW(),
+         applied_by = $5, applied_host = $6, fly_version = $7, execution_ms = $8
+     WHERE name = $1 RETURNING *";
+pub(crate) static INSERT_HISTORY: &str =
+    "INSERT INTO migration_history (name, event, checksum, applied_by, applied_host, fly_version, execution_ms)
+     VALUES ($1, $2, $3, $4, $5, $6, $7)";
+
+/// A connection fly can migrate through: a [`Client`], a [`Transaction`], a
+/// mutable reference to either, or with the `r2d2` feature a pooled
+/// connection. Each migration runs in a transaction of its own, or in a
+/// savepoint when the connection is already a transaction, in which case
+/// nothing is permanent until the caller commits it.
+pub trait Connection {
+    type Client: GenericClient;
+
+    fn client(&mut self) -> &mut Self::Client;
+}
+
+impl Connection for Client {
+    type Client = Client;
+
+    fn client(&mut self) -> &mut Client {
+        self
+    }
+}
+
+impl<'a> Connection for Transaction<'a> {
+    type Client = Transaction<'a>;
+
+    fn client(&mut self) -> &mut Transaction<'a> {
+        self
+    }
+}
+
+impl<C: Connection + ?Sized> Connection for &mut C {
+    type Client = C::Client;
+
+    fn client(&mut self) -> &mut C::Client {
+        (**self).client()
+    }
+}
+
+#[cfg(feature = "r2d2")]
+impl<M> Connection for r2d2::PooledConnection<M>
+where
+    M: r2d2::ManageConnection<Connection = Client>,
+{
+    type Client = Client;
+
+    fn client(&mut self) -> &mut Client {
+        self
+    }
+}
+
+pub struct Db<C: Connection = Client> {
+    client: C,
+    timeouts: Timeouts,
+    lock_retry: RetryPolicy,
 }
 
 impl Db {
     pub fn connect(config: &Config) -> Result<Db> {
-        let client = Client::connect(&config.connection_string, NoTls)?;
-        Ok(Db { client })
+        let client =
+            Client::connect(&config.connection_string, NoTls).map_err(Error::Connection)?;
+        Ok(Db::from_client(client, config))
+    }
+}
+
+impl<C: Connection> Db<C> {
+    /// Wraps an existing connection. `config.connection_string` is unused.
+    pub fn from_client(client: C, config: &Config) -> Db<C> {
+        Db {
+            client,
+            timeouts: config.timeouts,
+            lock_retry: config.lock_retry,
+        }
+    }
+
+    /// Gives back the connection.
+    pub fn into_inner(self) -> C {
+        self.client
     }
 
     pub fn create_migrations_table(&mut self) -> Result<()> {
-        self.client.batch_execute(CREATE_MIGRATIONS_TABLE)?;
+        let client = self.client.client();
+        client.batch_execute(CREATE_MIGRATIONS_TABLE)?;
+        client.batch_execute(UPGRADE_MIGRATIONS_TABLE)?;
+        client.batch_execute(CREATE_HISTORY_TABLE)?;
+        Ok(())
+    }
+
+    /// The tenant schemas to migrate, in order.
+    pub fn schemas(&mut self, tenants: &Tenants) -> Result<Vec<String>> {
+        match tenants {
+            Tenants::Schemas(schemas) => Ok(schemas.clone()),
+            Tenants::Query(query) => {
+                let rows = self.client.client().query(query, &[])?;
+                rows.iter().map(|row| Ok(row.try_get(0)?)).collect()
+            }
+        }
+    }
+
+    /// Points the connection at a tenant schema, so that migrations, their
+    /// bookkeeping tables and unqualified names all go there. Names that
+    /// aren't found in it are still looked up in `public`.
+    pub fn set_schema(&mut self, schema: &str) -> Result<()> {
+        let client = self.client.client();
+        let exists = client.query_opt(SCHEMA_EXISTS, &[&schema])?.is_some();
+        if !exists {
+            return Err(Error::SchemaNotFound {
+                schema: schema.to_string(),
+            });
+        }
+        client.execute(SET_SEARCH_PATH, &[&schema])?;
         Ok(())
     }
 
     pub fn list(&mut self) -> Result<Vec<MigrationWithMeta>> {
-        let rows = self.client.query("SELECT * FROM migrations", &[])?;
+        let rows = self.client.client().query(SELECT_MIGRATIONS, &[])?;
         let migrations = rows
             .iter()
             .map(parse_migration_with_meta)
@@ -39,49 +189,508 @@ impl Db {
         Ok(migrations)
     }
 
-    /// Panics if the INSERT statement does not return 1 row.
+    /// Lists recorded history events, oldest first, optionally only those of
+    /// one migration.
+    pub fn history(&mut self, name: Option<&str>) -> Result<Vec<HistoryEntry>> {
+        let rows = self.client.client().query(SELECT_HISTORY, &[&name])?;
+        rows.iter().map(parse_history_entry).collect()
+    }
+
+    /// The versions another migration tool recorded as applied, in its own
+    /// table.
+    pub fn imported_versions(&mut self, tool: Tool) -> Result<Vec<String>> {
+        let client = self.client.client();
+        if client.query_opt(TABLE_EXISTS, &[&tool.table()])?.is_none() {
+            return Err(Error::ImportTableNotFound {
+                table: tool.table().to_string(),
+            });
+        }
+        let rows = client.query(tool.applied_versions_query(), &[])?;
+        rows.iter().map(|row| Ok(row.try_get(0)?)).collect()
+    }
+
     pub fn run(&mut self, migration: &Migration) -> Result<MigrationWithMeta> {
         debug!("inserting migration {:?}", migration);
-        let mut transaction = self.client.transaction()?;
-        transaction.batch_execute(&migration.up_sql)?;
-        let rows = transaction.query(
-            "INSERT INTO migrations (name, up_sql, down_sql) VALUES ($1, $2, $3) RETURNING *",
-            &[&migration.name, &migration.up_sql, &migration.down_sql],
-        )?;
-        let [ref row] = rows[..] else {
-            panic!("postgres inserted {} elements, expected 1", rows.len());
-        };
-        let migration = parse_migration_with_meta(row)?;
+        let applied = self.in_transaction(migration, Change::Apply)?;
+        expect_applied(migration, applied)
+    }
+
+    /// Records a migration as applied without running it, e.g. for a
+    /// database whose schema was created some other way.
+    pub fn baseline(&mut self, migration: &Migration) -> Result<MigrationWithMeta> {
+        debug!("baselining migration {:?}", migration);
+        let applied = self.in_transaction(migration, Change::Baseline)?;
+        expect_applied(migration, applied)
+    }
+
+    /// Re-runs a repeatable migration and updates its stored sql.
+    pub fn reapply(&mut self, migration: &Migration) -> Result<MigrationWithMeta> {
+        debug!("reapplying migration {:?}", migration);
+        let applied = self.in_transaction(migration, Change::Reapply)?;
+        expect_applied(migration, applied)
+    }
 
-        transaction.commit()?;
-        Ok(migration)
+    /// Replaces the stored sql of an applied migration with `migration`'s,
+    /// without running anything, so that it's no longer reported as changed.
+    pub fn repair(&mut self, migration: &Migration) -> Result<MigrationWithMeta> {
+        debug!("repairing migration {:?}", migration);
+        let applied = self.in_transaction(migration, Change::Repair)?;
+        expect_applied(migration, applied)
     }
 
     pub fn rollback_migration(&mut self, migration: &Migration) -> Result<()> {
         debug!("rolling back migration {:?}", migration);
-        let mut transaction = self.client.transaction()?;
-        transaction.batch_execute(&migration.down_sql)?;
-        transaction.execute("DELETE FROM migrations WHERE name = $1", &[&migration.name])?;
-        transaction.commit()?;
+        self.in_transaction(migration, Change::Rollback)?;
+        Ok(())
+    }
+
+    /// Runs a sql hook's statements, without any bookkeeping.
+    pub fn run_hook(&mut self, hook: &Migration) -> Result<()> {
+        debug!("running hook {:?}", hook);
+        self.in_transaction(hook, Change::Hook)?;
         Ok(())
     }
+
+    // Makes a change in a transaction with the migration's timeouts set. If
+    // a lock timeout fires, the transaction is rolled back and, if the retry
+    // policy allows, tried again after a delay.
+    fn in_transaction(
+        &mut self,
+        migration: &Migration,
+        change: Change,
+    ) -> Result<Option<MigrationWithMeta>> {
+        let timeouts = migration.timeouts.or(&self.timeouts);
+        let mut retries = Retries::new(migration, self.lock_retry);
+        loop {
+            let result = self
+                .client
+                .client()
+                .transaction()
+                .map_err(Error::from)
+                .and_then(|mut transaction| {
+                    let replaced = set_timeouts(&mut transaction, &timeouts)?;
+                    let applied = make_change(&mut transaction, migration, change)?;
+                    restore_settings(&mut transaction, &replaced)?;
+                    transaction
+                        .commit()
+                        .map_err(|e| commit_error(migration, e))?;
+                    Ok(applied)
+                })
+                .map_err(|e| transaction_error(migration, e));
+            match result {
+                Err(e) => std::thread::sleep(retries.next(e)?),
+                applied => return applied,
+            }
+        }
+    }
+}
+
+// Runs a change's section, if it has one, then records it.
+fn make_change(
+    transaction: &mut Transaction,
+    migration: &Migration,
+    change: Change,
+) -> Result<Option<MigrationWithMeta>> {
+    let execution_ms = match change.section() {
+        Some(section) => Some(timed(|| execute(transaction, migration, section))?),
+        None => None,
+    };
+    let record = Record::new(migration, change, execution_ms);
+    let mut applied = None;
+    if let Some((statement, params)) = record.migration_statement() {
+        let rows = transaction
+            .query(statement, &params)
+            .map_err(|e| bookkeeping_error(migration, e))?;
+        applied = record.applied(&rows)?;
+    }
+    if let Some((statement, params)) = record.history_statement() {
+        transaction
+            .execute(statement, &params)
+            .map_err(|e| bookkeeping_error(migration, e))?;
+    }
+    Ok(applied)
+}
+
+// Applies timeouts for the rest of the transaction only, returning the
+// settings they replaced. When the transaction is a savepoint, local settings
+// outlive it until the caller's transaction ends, so they must be restored
+// before it's released.
+fn set_timeouts(
+    transaction: &mut Transaction,
+    timeouts: &Timeouts,
+) -> Result<Vec<(&'static str, String)>> {
+    let mut replaced = vec![];
+    for (setting, value) in timeout_settings(timeouts) {
+        let current = transaction.query_one(CURRENT_SETTING, &[&setting])?;
+        replaced.push((setting, current.try_get(0)?));
+        transaction.execute(SET_CONFIG_LOCAL, &[&setting, &value])?;
+    }
+    Ok(replaced)
 }
 
-fn parse_migration_with_meta(row: &Row) -> Result<MigrationWithMeta> {
+fn restore_settings(
+    transaction: &mut Transaction,
+    settings: &[(&'static str, String)],
+) -> Result<()> {
+    for (setting, value) in settings {
+        transaction.execute(SET_CONFIG_LOCAL, &[setting, value])?;
+    }
+    Ok(())
+}
+
+// The settings, and their values, that apply `timeouts`.
+pub(crate) fn timeout_settings(timeouts: &Timeouts) -> Vec<(&'static str, String)> {
+    [
+        ("lock_timeout", timeouts.lock_timeout),
+        ("statement_timeout", timeouts.statement_timeout),
+    ]
+    .into_iter()
+    .filter_map(|(setting, timeout)| Some((setting, format!("{}ms", timeout?.as_millis()))))
+    .collect()
+}
+
+pub(crate) fn is_lock_timeout(error: &Error) -> bool {
+    let source = match error {
+        Error::Pg(e) => e,
+        Error::MigrationStatementFailed(failure) => &failure.source,
+        Error::Bookkeeping { source, .. } => source,
+        _ => return false,
+    };
+    source.code() == Some(&SqlState::LOCK_NOT_AVAILABLE)
+}
+
+// Counts the attempts at a migration's transaction, deciding after each
+// failure whether to try again.
+pub(crate) struct Retries<'a> {
+    migration: &'a Migration,
+    policy: RetryPolicy,
+    retry: u32,
+}
+
+impl<'a> Retries<'a> {
+    pub(crate) fn new(migration: &'a Migration, policy: RetryPolicy) -> Retries<'a> {
+        Retries {
+            migration,
+            policy,
+            retry: 0,
+        }
+    }
+
+    // How long to wait before retrying after `error`, or the error to give
+    // up with. Only lock timeouts are retried.
+    pub(crate) fn next(&mut self, error: Error) -> Result<Duration> {
+        if !is_lock_timeout(&error) {
+            return Err(error);
+        }
+        if self.retry >= self.policy.retries {
+            return Err(Error::LockTimeout {
+                name: self.migration.name.clone(),
+                attempts: self.retry + 1,
+                source: Box::new(error),
+            });
+        }
+        let delay = self.policy.delay(self.retry);
+        self.retry += 1;
+        warn!(
+            "{} timed out waiting for a lock, retrying in {:?} ({} of {})",
+            self.migration.name, delay, self.retry, self.policy.retries
+        );
+        Ok(delay)
+    }
+}
+
+pub(crate) fn bookkeeping_error(migration: &Migration, source: postgres::Error) -> Error {
+    Error::Bookkeeping {
+        name: migration.name.clone(),
+        source,
+    }
+}
+
+// Classifies a failed COMMIT: an integrity constraint violation there comes
+// from a deferred constraint of the migration's sql, and anything else is
+// left to `transaction_error`.
+pub(crate) fn commit_error(migration: &Migration, error: postgres::Error) -> Error {
+    match error.as_db_error() {
+        Some(db_error) if db_error.code().code().starts_with("23") => {
+            Error::MigrationCommitFailed {
+                name: migration.name.clone(),
+                message: db_error.message().to_string(),
+                source: error,
+            }
+        }
+        _ => Error::Pg(error),
+    }
+}
+
+// Classifies the failures of a migration's transaction that aren't its own
+// sql's, e.g. of BEGIN, COMMIT or setting timeouts: a lost connection is a
+// connection error, anything else a bookkeeping one.
+pub(crate) fn transaction_error(migration: &Migration, error: Error) -> Error {
+    match error {
+        Error::Pg(source) if source.is_closed() => Error::Connection(source),
+        Error::Pg(source) => bookkeeping_error(migration, source),
+        error => error,
+    }
+}
+
+// What a transaction does to a migration. Both `Db` and `AsyncDb` run the
+// same plan for each, and differ only in how they talk to the database.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Change {
+    Apply,
+    Baseline,
+    Reapply,
+    Repair,
+    Rollback,
+    Hook,
+}
+
+impl Change {
+    // The section of the migration to run, if any.
+    pub(crate) fn section(self) -> Option<Section> {
+        match self {
+            Change::Apply | Change::Reapply | Change::Hook => Some(Section::Up),
+            Change::Rollback => Some(Section::Down),
+            Change::Baseline | Change::Repair => None,
+        }
+    }
+
+    fn event(self) -> Option<HistoryEvent> {
+        match self {
+            Change::Apply => Some(HistoryEvent::Apply),
+            Change::Baseline => Some(HistoryEvent::Baseline),
+            Change::Reapply => Some(HistoryEvent::Reapply),
+            Change::Repair => Some(HistoryEvent::Repair),
+            Change::Rollback => Some(HistoryEvent::Rollback),
+            Change::Hook => None,
+        }
+    }
+}
+
+type Params<'a> = Vec<&'a (dyn ToSql + Sync)>;
+
+// The bookkeeping of a change: its statements against the migrations and
+// history tables, with their parameters.
+pub(crate) struct Record<'a> {
+    migration: &'a Migration,
+    change: Change,
+    kind: &'static str,
+    event: Option<&'static str>,
+    checksum: String,
+    audit: Audit,
+    execution_ms: Option<i64>,
+}
+
+impl<'a> Record<'a> {
+    pub(crate) fn new(
+        migration: &'a Migration,
+        change: Change,
+        execution_ms: Option<i64>,
+    ) -> Record<'a> {
+        Record {
+            migration,
+            change,
+            kind: migration.kind.as_str(),
+            event: change.event().as_ref().map(HistoryEvent::as_str),
+            checksum: migration.checksum(),
+            audit: Audit::current(),
+            execution_ms,
+        }
+    }
+
+    // The statement that inserts, updates or deletes the migration's row.
+    pub(crate) fn migration_statement(&self) -> Option<(&'static str, Params<'_>)> {
+        let statement = match self.change {
+            Change::Apply | Change::Baseline => INSERT_MIGRATION,
+            Change::Reapply | Change::Repair => UPDATE_MIGRATION,
+            Change::Rollback => return Some((DELETE_MIGRATION, vec![&self.migration.name])),
+            Change::Hook => return None,
+        };
+        let params: Params = vec![
+            &self.migration.name,
+            &self.migration.up_sql,
+            &self.migration.down_sql,
+            &self.kind,
+            &self.audit.applied_by,
+            &self.audit.applied_host,
+            &self.audit.fly_version,
+            &self.execution_ms,
+        ];
+        Some((statement, params))
+    }
+
+    // The statement that appends the change to the history table, in the
+    // same transaction as the change itself.
+    pub(crate) fn history_statement(&self) -> Option<(&'static str, Params<'_>)> {
+        let event = self.event.as_ref()?;
+        let params: Params = vec![
+            &self.migration.name,
+            event,
+            &self.checksum,
+            &self.audit.applied_by,
+            &self.audit.applied_host,
+            &self.audit.fly_version,
+            &self.execution_ms,
+        ];
+        Some((INSERT_HISTORY, params))
+    }
+
+    // Parses the row `migration_statement` returned, for changes that write
+    // one.
+    pub(crate) fn applied(&self, rows: &[Row]) -> Result<Option<MigrationWithMeta>> {
+        if matches!(self.change, Change::Rollback | Change::Hook) {
+            return Ok(None);
+        }
+        let [ref row] = rows[..] else {
+            return Err(Error::UnexpectedRowCount {
+                name: self.migration.name.clone(),
+                rows: rows.len(),
+            });
+        };
+        parse_migration_with_meta(row).map(Some)
+    }
+}
+
+// The row of a change that writes one, which `Record::applied` guarantees.
+pub(crate) fn expect_applied(
+    migration: &Migration,
+    applied: Option<MigrationWithMeta>,
+) -> Result<MigrationWithMeta> {
+    applied.ok_or_else(|| Error::UnexpectedRowCount {
+        name: migration.name.clone(),
+        rows: 0,
+    })
+}
+
+// Who is applying migrations, recorded alongside each one.
+struct Audit {
+    applied_by: String,
+    applied_host: Option<String>,
+    fly_version: &'static str,
+}
+
+impl Audit {
+    fn current() -> Audit {
+        Audit {
+            applied_by: whoami::username(),
+            applied_host: whoami::fallible::hostname().ok(),
+            fly_version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+}
+
+// Runs `f`, returning how long it took in milliseconds.
+fn timed(f: impl FnOnce() -> Result<()>) -> Result<i64> {
+    let start = Instant::now();
+    f()?;
+    Ok(start.elapsed().as_millis() as i64)
+}
+
+// Runs one section of a migration a statement at a time, so that a failure
+// can be traced back to a statement and line in the migration file.
+fn execute(transaction: &mut Transaction, migration: &Migration, section: Section) -> Result<()> {
+    for (i, statement) in sql::statements(migration.sql(section)).iter().enumerate() {
+        debug!("executing statement {}: {}", i + 1, statement.sql);
+        transaction
+            .batch_execute(statement.sql)
+            .map_err(|e| statement_error(migration, section, i + 1, statement, e))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn statement_error(
+    migration: &Migration,
+    section: Section,
+    index: usize,
+    statement: &Statement,
+    error: postgres::Error,
+) -> Error {
+    // Statement lines are relative to the section's sql.
+    let offset = migration.first_line(section) - 1;
+    let db_error = error.as_db_error();
+    let error_line = db_error
+        .and_then(|e| e.position())
+        .and_then(|position| match position {
+            ErrorPosition::Original(position) => Some(*position as usize),
+            ErrorPosition::Internal { .. } => None,
+        })
+        .map(|position| {
+            let newlines = statement
+                .sql
+                .chars()
+                .take(position.saturating_sub(1))
+                .filter(|c| *c == '\n')
+                .count();
+            offset + statement.first_line + newlines
+        });
+
+    Error::MigrationStatementFailed(Box::new(StatementFailure {
+        name: migration.name.clone(),
+        section,
+        index,
+        statement: statement.sql.to_string(),
+        first_line: offset + statement.first_line,
+        last_line: offset + statement.last_line,
+        error_line,
+        message: db_error.map_or_else(|| error.to_string(), |e| e.message().to_string()),
+        detail: db_error.and_then(|e| e.detail()).map(|s| s.to_string()),
+        hint: db_error.and_then(|e| e.hint()).map(|s| s.to_string()),
+        source: error,
+    }))
+}
+
+pub(crate) fn parse_migration_with_meta(row: &Row) -> Result<MigrationWithMeta> {
     let up_sql = row.try_get::<_, String>("up_sql")?;
     let down_sql = row.try_get::<_, String>("down_sql")?;
     let name = row.try_get::<_, String>("name")?;
+    let kind = row.try_get::<_, &str>("kind")?.parse::<MigrationKind>()?;
 
     let migration = Migration {
         up_sql,
         down_sql,
         name,
+        kind,
+        lines: SourceLines::default(),
+        timeouts: Timeouts::default(),
+        template: false,
+        lint_ignore: Vec::new(),
     };
 
     let id = row.try_get::<_, i32>("id")?;
     let created_at = row.try_get::<_, SystemTime>("created_at")?;
 
-    let meta = MigrationMeta { id, created_at };
+    // Rows written by older versions of fly have no audit information.
+    let applied_by = row.try_get::<_, Option<String>>("applied_by")?;
+    let applied_host = row.try_get::<_, Option<String>>("applied_host")?;
+    let fly_version = row.try_get::<_, Option<String>>("fly_version")?;
+    let execution_time = row
+        .try_get::<_, Option<i64>>("execution_ms")?
+        .map(|ms| Duration::from_millis(ms as u64));
+
+    let meta = MigrationMeta {
+        id,
+        created_at,
+        applied_by,
+        applied_host,
+        fly_version,
+        execution_time,
+    };
 
     Ok(MigrationWithMeta { migration, meta })
 }
+
+pub(crate) fn parse_history_entry(row: &Row) -> Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        event: row.try_get::<_, &str>("event")?.parse()?,
+        checksum: row.try_get("checksum")?,
+        applied_by: row.try_get("applied_by")?,
+        applied_host: row.try_get("applied_host")?,
+        fly_version: row.try_get("fly_version")?,
+        execution_time: row
+            .try_get::<_, Option<i64>>("execution_ms")?
+            .map(|ms| Duration::from_millis(ms as u64)),
+        created_at: row.try_get("created_at")?,
+    })
+}
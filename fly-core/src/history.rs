@@ -0,0 +1,94 @@
+use crate::error::{Error, Result};
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+/// Something that happened to a migration, as recorded in the append-only
+/// `migration_history` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryEvent {
+    /// The up sql was run.
+    Apply,
+    /// A changed repeatable migration's up sql was run again.
+    Reapply,
+    /// The down sql was run and the migration removed from `migrations`.
+    Rollback,
+    /// The stored sql was replaced with the file's, without running anything.
+    Repair,
+    /// The migration was marked as applied without running it.
+    Baseline,
+}
+
+impl HistoryEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HistoryEvent::Apply => "apply",
+            HistoryEvent::Reapply => "reapply",
+            HistoryEvent::Rollback => "rollback",
+            HistoryEvent::Repair => "repair",
+            HistoryEvent::Baseline => "baseline",
+        }
+    }
+}
+
+impl Display for HistoryEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for HistoryEvent {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "apply" => Ok(HistoryEvent::Apply),
+            "reapply" => Ok(HistoryEvent::Reapply),
+            "rollback" => Ok(HistoryEvent::Rollback),
+            "repair" => Ok(HistoryEvent::Repair),
+            "baseline" => Ok(HistoryEvent::Baseline),
+            _ => Err(Error::UnknownHistoryEvent {
+                event: s.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub id: i32,
+    pub name: String,
+    pub event: HistoryEvent,
+    /// Checksum of the migration's sql at the time of the event.
+    pub checksum: String,
+    pub applied_by: Option<String>,
+    pub applied_host: Option<String>,
+    pub fly_version: Option<String>,
+    /// How long the sql took to run, for events that ran any.
+    pub execution_time: Option<Duration>,
+    pub created_at: SystemTime,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_round_trip() {
+        for event in [
+            HistoryEvent::Apply,
+            HistoryEvent::Reapply,
+            HistoryEvent::Rollback,
+            HistoryEvent::Repair,
+            HistoryEvent::Baseline,
+        ] {
+            assert_eq!(event.as_str().parse::<HistoryEvent>().unwrap(), event);
+        }
+        assert_eq!(
+            "drop".parse::<HistoryEvent>().err().unwrap().to_string(),
+            "unknown history event drop"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+#[cfg(feature = "async")]
+use crate::async_db::{AsyncConnection, AsyncDb};
+use crate::config::Config;
+use crate::db::{Connection, Db};
+use crate::error::{Error, Result};
+use crate::file::{self, HOOKS_DIR};
+use crate::migration::{Migration, Section};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::process::{Command, ExitStatus};
+use tracing::{debug, info};
+
+/// When a hook runs, relative to the migrations being applied or rolled
+/// back by a single `up` or `down`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HookPoint {
+    BeforeAll,
+    BeforeEach,
+    AfterEach,
+    AfterAll,
+}
+
+impl HookPoint {
+    pub const ALL: [HookPoint; 4] = [
+        HookPoint::BeforeAll,
+        HookPoint::BeforeEach,
+        HookPoint::AfterEach,
+        HookPoint::AfterAll,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookPoint::BeforeAll => "before_all",
+            HookPoint::BeforeEach => "before_each",
+            HookPoint::AfterEach => "after_each",
+            HookPoint::AfterAll => "after_all",
+        }
+    }
+
+    /// The environment variable holding this hook's shell command, e.g.
+    /// `MIGRATE_HOOK_AFTER_ALL`.
+    pub fn env_var(&self) -> String {
+        format!("MIGRATE_HOOK_{}", self.as_str().to_uppercase())
+    }
+}
+
+impl Display for HookPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// What the hooks are running around.
+#[derive(Debug, Clone, Copy)]
+pub struct HookContext<'a> {
+    pub section: Section,
+    /// Every migration in the batch.
+    pub migrations: &'a [&'a Migration],
+    /// The migration being run, for `before_each` and `after_each`.
+    pub migration: Option<&'a Migration>,
+}
+
+/// SQL files in `MIGRATE_DIR/hooks/` (e.g. `after_each.sql`), run in the
+/// database, and shell commands, run locally. At each point the SQL hook
+/// runs first.
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    sql: HashMap<HookPoint, Migration>,
+    commands: HashMap<HookPoint, String>,
+}
+
+impl Hooks {
+    pub fn load(config: &Config) -> Result<Hooks> {
+        let hooks_dir = config.migrate_dir.join(HOOKS_DIR);
+        let mut sql = HashMap::new();
+        for point in HookPoint::ALL {
+            let path = hooks_dir.join(format!("{}.sql", point));
+            if path.is_file() {
+                let mut hook = file::parse_repeatable_migration_from_file(&path)?;
+                hook.name = format!("{}/{}", HOOKS_DIR, hook.name);
+                sql.insert(point, hook);
+            }
+        }
+        Ok(Hooks {
+            sql,
+            commands: config.hook_commands.clone(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sql.is_empty() && self.commands.is_empty()
+    }
+
+    /// Like [`Hooks::run`], for the async migrator. Shell commands run on
+    /// tokio's process driver rather than blocking the runtime.
+    #[cfg(feature = "async")]
+    pub async fn run_async<C: AsyncConnection>(
+        &self,
+        point: HookPoint,
+        db: &mut AsyncDb<C>,
+        context: HookContext<'_>,
+    ) -> Result<()> {
+        if let Some(hook) = self.sql.get(&point) {
+            info!("running {}", hook.name);
+            db.run_hook(hook).await?;
+        }
+        if let Some(command) = self.commands.get(&point) {
+            info!("running {} hook: {}", point, command);
+            let cmd = build_command(point, command, &context);
+            debug!("running {:?}", cmd);
+            let status = tokio::process::Command::from(cmd).status().await;
+            check_status(point, status)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the hooks for `point`. A failing hook is an error, so a failing
+    /// `before_*` hook stops the migration from running.
+    pub fn run<C: Connection>(
+        &self,
+        point: HookPoint,
+        db: &mut Db<C>,
+        context: &HookContext,
+    ) -> Result<()> {
+        if let Some(hook) = self.sql.get(&point) {
+            info!("running {}", hook.name);
+            db.run_hook(hook)?;
+        }
+        if let Some(command) = self.commands.get(&point) {
+            info!("running {} hook: {}", point, command);
+            run_command(point, command, context)?;
+        }
+        Ok(())
+    }
+}
+
+fn run_command(point: HookPoint, command: &str, context: &HookContext) -> Result<()> {
+    let mut cmd = build_command(point, command, context);
+    debug!("running {:?}", cmd);
+    check_status(point, cmd.status())
+}
+
+// `sh -c command`, with the context in the environment.
+fn build_command(point: HookPoint, command: &str, context: &HookContext) -> Command {
+    let names = context
+        .migrations
+        .iter()
+        .map(|m| m.name.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .env("FLY_HOOK", point.as_str())
+        .env("FLY_DIRECTION", context.section.to_string())
+        .env("FLY_MIGRATIONS", names);
+    if let Some(migration) = context.migration {
+        cmd.env("FLY_MIGRATION_NAME", &migration.name)
+            .env("FLY_MIGRATION_KIND", migration.kind.as_str());
+    }
+    cmd
+}
+
+fn check_status(point: HookPoint, status: std::io::Result<ExitStatus>) -> Result<()> {
+    let status = status.map_err(|source| Error::HookNotStarted {
+        hook: point.to_string(),
+        source,
+    })?;
+    if !status.success() {
+        return Err(Error::HookFailed {
+            hook: point.to_string(),
+            status,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+//! Taking over a database from another migration tool. The versions the tool
+//! recorded as applied are matched to migrations by version, and those
+//! migrations are baselined, so that `fly up` doesn't run them again.
+
+use crate::error::{Error, Result};
+use crate::migration::Migration;
+use crate::naming;
+use crate::planner::ApplicationState;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// A migration tool whose bookkeeping table fly can import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    /// Rails' `schema_migrations`.
+    Rails,
+    /// Diesel's `__diesel_schema_migrations`.
+    Diesel,
+    /// sqlx's `_sqlx_migrations`.
+    Sqlx,
+    /// Flyway's `flyway_schema_history`.
+    Flyway,
+    /// goose's `goose_db_version`.
+    Goose,
+}
+
+impl Tool {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tool::Rails => "rails",
+            Tool::Diesel => "diesel",
+            Tool::Sqlx => "sqlx",
+            Tool::Flyway => "flyway",
+            Tool::Goose => "goose",
+        }
+    }
+
+    /// The table the tool records applied migrations in.
+    pub fn table(&self) -> &'static str {
+        match self {
+            Tool::Rails => "schema_migrations",
+            Tool::Diesel => "__diesel_schema_migrations",
+            Tool::Sqlx => "_sqlx_migrations",
+            Tool::Flyway => "flyway_schema_history",
+            Tool::Goose => "goose_db_version",
+        }
+    }
+
+    // Selects the versions the tool considers applied, as text.
+    pub(crate) fn applied_versions_query(&self) -> &'static str {
+        match self {
+            Tool::Rails => "SELECT version::text FROM schema_migrations",
+            Tool::Diesel => "SELECT version::text FROM __diesel_schema_migrations",
+            Tool::Sqlx => "SELECT version::text FROM _sqlx_migrations WHERE success",
+            Tool::Flyway => {
+                "SELECT version FROM flyway_schema_history WHERE success AND version IS NOT NULL"
+            }
+            // goose appends a row for every apply and rollback, so only the
+            // latest row of each version counts. Version 0 is goose's own.
+            Tool::Goose => {
+                "SELECT version_id::text FROM (
+                   SELECT DISTINCT ON (version_id) version_id, is_applied
+                   FROM goose_db_version ORDER BY version_id, id DESC
+                 ) latest WHERE is_applied AND version_id > 0"
+            }
+        }
+    }
+
+    /// The version of a migration as the tool would record it, e.g.
+    /// `20240323120000` for diesel's `2024-03-23-120000_create_users`.
+    pub fn migration_version<'a>(&self, name: &'a str) -> Option<&'a str> {
+        match self {
+            Tool::Diesel => {
+                let (version, _) = name.split_once('_')?;
+                version
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == '-')
+                    .then_some(version)
+            }
+            _ => naming::version(name),
+        }
+    }
+}
+
+impl Display for Tool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Tool {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rails" => Ok(Tool::Rails),
+            "diesel" => Ok(Tool::Diesel),
+            "sqlx" => Ok(Tool::Sqlx),
+            "flyway" => Ok(Tool::Flyway),
+            "goose" => Ok(Tool::Goose),
+            _ => Err(Error::UnknownImportTool {
+                tool: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// What [`Migrator::import`](crate::migrator::Migrator::import) did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Names of the pending migrations that were marked as applied.
+    pub imported: Vec<String>,
+    /// Versions the tool applied that no migration has.
+    pub unmatched: Vec<String>,
+}
+
+// Versions are compared ignoring dashes and leading zeros, so `001` matches
+// `0000000001` and diesel's `2024-03-23-120000` matches `20240323120000`.
+fn normalize(version: &str) -> String {
+    let digits = version.replace('-', "");
+    digits.trim_start_matches('0').to_string()
+}
+
+// The pending versioned migrations whose versions the tool applied, and the
+// applied versions that match no migration.
+pub(crate) fn to_import<'a>(
+    application_state: &'a [ApplicationState],
+    tool: Tool,
+    versions: &[String],
+) -> (Vec<&'a Migration>, Vec<String>) {
+    let applied = versions
+        .iter()
+        .map(|v| normalize(v))
+        .collect::<HashSet<_>>();
+    let known = application_state
+        .iter()
+        .filter(|application| !application.is_repeatable())
+        .filter_map(|application| tool.migration_version(application.name()))
+        .map(normalize)
+        .collect::<HashSet<_>>();
+    let to_import = application_state
+        .iter()
+        .filter_map(|application| match application {
+            ApplicationState::Pending { definition } if !definition.is_repeatable() => {
+                let version = tool.migration_version(&definition.name)?;
+                applied.contains(&normalize(version)).then_some(definition)
+            }
+            _ => None,
+        })
+        .collect();
+    let unmatched = versions
+        .iter()
+        .filter(|v| !known.contains(&normalize(v)))
+        .cloned()
+        .collect();
+    (to_import, unmatched)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pending(name: &str) -> ApplicationState {
+        ApplicationState::Pending {
+            definition: Migration::new(name, "select 1;", "select 1;"),
+        }
+    }
+
+    fn names(migrations: Vec<&Migration>) -> Vec<&str> {
+        migrations.into_iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tool_from_str() {
+        for tool in [
+            Tool::Rails,
+            Tool::Diesel,
+            Tool::Sqlx,
+            Tool::Flyway,
+            Tool::Goose,
+        ] {
+            assert_eq!(tool.as_str().parse::<Tool>().unwrap(), tool);
+        }
+        assert_eq!(
+            "liquibase".parse::<Tool>().err().unwrap().to_string(),
+            "unknown migration tool liquibase"
+        );
+    }
+
+    #[test]
+    fn test_to_import() {
+        let state = [
+            pending("0000000001_create_users.sql"),
+            pending("0000000002_create_posts.sql"),
+            pending("0000000003_create_tags.sql"),
+        ];
+        let versions = ["1".to_string(), "2".to_string(), "7".to_string()];
+        let (to_import, unmatched) = to_import(&state, Tool::Goose, &versions);
+        assert_eq!(
+            names(to_import),
+            ["0000000001_create_users.sql", "0000000002_create_posts.sql"]
+        );
+        assert_eq!(unmatched, ["7"]);
+    }
+
+    #[test]
+    fn test_to_import_diesel() {
+        let state = [
+            pending("2024-03-23-120000_create_users"),
+            pending("2024-03-24-090000_create_posts"),
+        ];
+        let versions = ["20240323120000".to_string()];
+        let (to_import, unmatched) = to_import(&state, Tool::Diesel, &versions);
+        assert_eq!(names(to_import), ["2024-03-23-120000_create_users"]);
+        assert!(unmatched.is_empty());
+    }
+}
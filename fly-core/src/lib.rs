@@ -1,6 +1,20 @@
+#[cfg(feature = "async")]
+pub mod async_db;
+#[cfg(feature = "async")]
+pub mod async_migrator;
 pub mod config;
+pub mod convert;
 pub mod db;
-pub mod error;
-pub mod file;
-pub mod migration;
+#[cfg(feature = "embed")]
+pub use fly_macros::embed_migrations;
+pub use fly_parse::{error, file, migration, naming};
+pub mod history;
+pub mod hooks;
+pub mod import;
+pub mod lint;
+pub mod migrator;
 pub mod planner;
+mod repair;
+pub mod source;
+use fly_parse::sql;
+pub mod template;
@@ -0,0 +1,276 @@
+use crate::migration::Migration;
+use sqlparser::ast::{AlterTableOperation, ColumnOption, ObjectName, Statement};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::Token;
+use std::collections::HashSet;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub severity: Severity,
+}
+
+/// The statement could not be parsed, so it was not checked.
+pub static UNPARSEABLE: Rule = Rule {
+    id: "FLY000",
+    name: "unparseable-statement",
+    severity: Severity::Warning,
+};
+
+/// `ALTER TABLE ... ADD COLUMN ... DEFAULT` rewrites the whole table under an
+/// `ACCESS EXCLUSIVE` lock before postgres 11, and still does for volatile
+/// defaults.
+pub static ADD_COLUMN_DEFAULT: Rule = Rule {
+    id: "FLY001",
+    name: "add-column-default",
+    severity: Severity::Warning,
+};
+
+/// `CREATE INDEX` without `CONCURRENTLY` blocks writes to the table until the
+/// index is built.
+pub static CREATE_INDEX_NOT_CONCURRENT: Rule = Rule {
+    id: "FLY002",
+    name: "create-index-not-concurrent",
+    severity: Severity::Warning,
+};
+
+/// `DROP COLUMN` breaks any running code still reading the column; the code
+/// should stop using it in an earlier deploy.
+pub static DROP_COLUMN: Rule = Rule {
+    id: "FLY003",
+    name: "drop-column",
+    severity: Severity::Error,
+};
+
+pub static RULES: [&Rule; 4] = [
+    &UNPARSEABLE,
+    &ADD_COLUMN_DEFAULT,
+    &CREATE_INDEX_NOT_CONCURRENT,
+    &DROP_COLUMN,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub migration: String,
+    pub rule: Rule,
+    /// Line of the offending statement in the migration file.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} {} ({}): {}",
+            self.migration,
+            self.line,
+            self.rule.severity,
+            self.rule.id,
+            self.rule.name,
+            self.message
+        )
+    }
+}
+
+/// Checks a migration's up sql for statements that are risky to run against
+/// a live database. Rules listed in its `-- fly:lint-ignore` directives are
+/// left out.
+pub fn lint(migration: &Migration) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut finding = |rule: &Rule, line: usize, message: String| {
+        if !migration.lint_ignore.iter().any(|id| id == rule.id) {
+            findings.push(Finding {
+                migration: migration.name.clone(),
+                rule: *rule,
+                line,
+                message,
+            });
+        }
+    };
+
+    let dialect = PostgreSqlDialect {};
+    let mut parser = match Parser::new(&dialect).try_with_sql(&migration.up_sql) {
+        Ok(parser) => parser,
+        Err(e) => {
+            finding(&UNPARSEABLE, migration.lines.up, e.to_string());
+            return findings;
+        }
+    };
+
+    // Tables created by this migration are new and empty, so locking or
+    // rewriting them is harmless.
+    let mut created_tables = HashSet::new();
+
+    loop {
+        while parser.consume_token(&Token::SemiColon) {}
+        let next = parser.peek_token();
+        if next.token == Token::EOF {
+            break;
+        }
+        let line = migration.lines.up + next.span.start.line as usize - 1;
+
+        let statement = match parser.parse_statement() {
+            Ok(statement) => statement,
+            Err(e) => {
+                finding(&UNPARSEABLE, line, e.to_string());
+                // Skip to the next statement and carry on.
+                loop {
+                    let token = parser.next_token().token;
+                    if token == Token::SemiColon || token == Token::EOF {
+                        break;
+                    }
+                }
+                continue;
+            }
+        };
+
+        match statement {
+            Statement::CreateTable(create_table) => {
+                created_tables.insert(table_key(&create_table.name));
+            }
+            Statement::CreateIndex(create_index)
+                if !create_index.concurrently
+                    && !created_tables.contains(&table_key(&create_index.table_name)) =>
+            {
+                finding(
+                    &CREATE_INDEX_NOT_CONCURRENT,
+                    line,
+                    format!(
+                        "index on {} is not created concurrently and will block writes",
+                        create_index.table_name
+                    ),
+                );
+            }
+            Statement::AlterTable {
+                name, operations, ..
+            } => {
+                let is_new = created_tables.contains(&table_key(&name));
+                for operation in operations {
+                    match operation {
+                        AlterTableOperation::AddColumn { column_def, .. } if !is_new => {
+                            let has_default = column_def
+                                .options
+                                .iter()
+                                .any(|o| matches!(o.option, ColumnOption::Default(_)));
+                            if has_default {
+                                finding(
+                                    &ADD_COLUMN_DEFAULT,
+                                    line,
+                                    format!(
+                                        "adding column {} with a default may rewrite {}",
+                                        column_def.name, name
+                                    ),
+                                );
+                            }
+                        }
+                        AlterTableOperation::DropColumn { column_name, .. } => {
+                            finding(
+                                &DROP_COLUMN,
+                                line,
+                                format!(
+                                    "dropping column {} of {} breaks code still using it",
+                                    column_name, name
+                                ),
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    findings
+}
+
+fn table_key(name: &ObjectName) -> String {
+    name.to_string().to_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn build_migration(up: &str) -> Migration {
+        Migration::new("1711200000-foo.sql", up, "")
+    }
+
+    fn rule_ids(findings: &[Finding]) -> Vec<(&'static str, usize)> {
+        findings.iter().map(|f| (f.rule.id, f.line)).collect()
+    }
+
+    #[test]
+    fn test_lint_clean_migration() {
+        let migration = build_migration(
+            "create table users (id int, active boolean default true);
+create index users_active on users (active);
+alter table users add column name text default 'anonymous';",
+        );
+        assert_eq!(lint(&migration), Vec::new());
+    }
+
+    #[test]
+    fn test_lint_risky_statements() {
+        let migration = build_migration(
+            "alter table users add column active boolean default true;
+
+create index users_active on users (active);
+create index concurrently users_name on users (name);
+alter table users
+  drop column legacy;",
+        );
+        let findings = lint(&migration);
+        assert_eq!(
+            rule_ids(&findings),
+            vec![("FLY001", 1), ("FLY002", 3), ("FLY003", 5)]
+        );
+        assert_eq!(
+            findings[2].to_string(),
+            "1711200000-foo.sql:5: error FLY003 (drop-column): dropping column legacy of users breaks code still using it"
+        );
+    }
+
+    #[test]
+    fn test_lint_ignore() {
+        let migration = Migration {
+            lint_ignore: vec!["FLY002".to_string(), "FLY003".to_string()],
+            ..build_migration(
+                "create index users_active on users (active);
+alter table users drop column legacy;",
+            )
+        };
+        assert_eq!(lint(&migration), Vec::new());
+    }
+
+    #[test]
+    fn test_lint_unparseable_statement() {
+        let migration = build_migration(
+            "this is not sql;
+alter table users drop column legacy;",
+        );
+        assert_eq!(
+            rule_ids(&lint(&migration)),
+            vec![("FLY000", 1), ("FLY003", 2)]
+        );
+    }
+}
@@ -0,0 +1,528 @@
+//! Running migrations from a program, e.g. on service startup:
+//!
+//! ```no_run
+//! use fly::migrator::Migrator;
+//!
+//! # fn main() -> fly::error::Result<()> {
+//! let mut migrator = Migrator::builder("db/migrate")
+//!     .connection_string("postgresql://app@localhost/app")
+//!     .build()?;
+//! let report = migrator.up()?;
+//! println!("applied {} migrations", report.applied.len());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::config::{Config, RetryPolicy};
+use crate::db::{Connection, Db};
+use crate::error::{Error, Result};
+use crate::history::HistoryEntry;
+use crate::hooks::{HookContext, HookPoint, Hooks};
+use crate::import::{to_import, ImportReport, Tool};
+use crate::migration::{Migration, Section, Timeouts};
+use crate::naming::NamingPolicy;
+use crate::planner::{get_migration_state, ApplicationState};
+use crate::repair::{to_baseline, to_repair};
+use crate::source::{Directory, MigrationSource};
+use postgres::Client;
+use std::path::Path;
+use tracing::{debug, info};
+
+/// Plans and runs the migrations in a directory against one database (or
+/// one tenant schema of it).
+pub struct Migrator<C: Connection = Client> {
+    config: Config,
+    db: Db<C>,
+    source: Box<dyn MigrationSource + Send + Sync>,
+}
+
+/// Builds a [`Migrator`] from a migrations directory and either a connection
+/// string or an open [`Client`]. Everything else defaults as in
+/// [`Config::new`]. With the `async` feature, `AsyncMigrator::builder` builds
+/// an `AsyncMigrator` the same way, from a tokio-postgres client.
+pub struct MigratorBuilder<K = Client> {
+    pub(crate) config: Config,
+    pub(crate) client: Option<K>,
+    pub(crate) source: Option<Box<dyn MigrationSource + Send + Sync>>,
+}
+
+impl<K> MigratorBuilder<K> {
+    pub(crate) fn new(migrate_dir: impl AsRef<Path>) -> MigratorBuilder<K> {
+        MigratorBuilder {
+            config: Config::new(migrate_dir, ""),
+            client: None,
+            source: None,
+        }
+    }
+
+    pub fn connection_string(mut self, connection_string: impl AsRef<str>) -> Self {
+        self.config.connection_string = connection_string.as_ref().to_owned();
+        self
+    }
+
+    /// Uses an existing connection instead of connecting.
+    pub fn client(mut self, client: K) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Reads migrations from `source` instead of the migrations directory.
+    pub fn source(mut self, source: impl MigrationSource + Send + Sync + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// Uses these migrations, e.g. from `fly::embed_migrations!`,
+    /// instead of reading the migrations directory.
+    pub fn migrations(self, migrations: Vec<Migration>) -> Self {
+        self.source(migrations)
+    }
+
+    pub fn naming(mut self, naming: NamingPolicy) -> Self {
+        self.config.naming = naming;
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.config.timeouts = timeouts;
+        self
+    }
+
+    pub fn lock_retry(mut self, lock_retry: RetryPolicy) -> Self {
+        self.config.lock_retry = lock_retry;
+        self
+    }
+
+    pub fn template_var(mut self, name: impl AsRef<str>, value: impl AsRef<str>) -> Self {
+        self.config
+            .template_vars
+            .insert(name.as_ref().to_owned(), value.as_ref().to_owned());
+        self
+    }
+
+    pub fn hook_command(mut self, point: HookPoint, command: impl AsRef<str>) -> Self {
+        self.config
+            .hook_commands
+            .insert(point, command.as_ref().to_owned());
+        self
+    }
+}
+
+impl MigratorBuilder {
+    pub fn build(self) -> Result<Migrator> {
+        let migrator = match self.client {
+            Some(client) => Migrator::from_client(client, self.config),
+            None => Migrator::connect(self.config)?,
+        };
+        Ok(match self.source {
+            Some(source) => Migrator { source, ..migrator },
+            None => migrator,
+        })
+    }
+}
+
+/// What [`Migrator::up`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpReport {
+    /// Names of the pending migrations that were applied, in order.
+    pub applied: Vec<String>,
+    /// Names of the changed repeatable migrations that were re-applied.
+    pub reapplied: Vec<String>,
+}
+
+impl UpReport {
+    /// Whether the database was already up to date.
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.reapplied.is_empty()
+    }
+}
+
+/// Which migration [`Migrator::down`] rolls back, and how.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownOptions {
+    /// The migration to roll back. Defaults to the latest applied versioned
+    /// migration.
+    pub name: Option<String>,
+    /// If the migration has changed or its file was removed, run the down sql
+    /// stored in the database.
+    pub recover: bool,
+    /// If the migration has changed, run the down sql in its file.
+    pub ignore_changed: bool,
+}
+
+/// What [`Migrator::down`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownReport {
+    /// The name of the migration rolled back, if there was one to roll back.
+    pub reverted: Option<String>,
+}
+
+/// What [`Migrator::redo`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RedoReport {
+    /// The name of the migration rolled back and applied again, if there was
+    /// one to roll back.
+    pub redone: Option<String>,
+}
+
+impl Migrator {
+    pub fn builder(migrate_dir: impl AsRef<Path>) -> MigratorBuilder {
+        MigratorBuilder::new(migrate_dir)
+    }
+
+    /// Connects to `config.connection_string`.
+    pub fn connect(config: Config) -> Result<Migrator> {
+        let db = Db::connect(&config)?;
+        Ok(Migrator {
+            source: Box::new(Directory::new(&config.migrate_dir)),
+            config,
+            db,
+        })
+    }
+}
+
+impl<C: Connection> Migrator<C> {
+    /// Migrates through an existing connection, e.g. a [`Client`] with your
+    /// own TLS setup, a pooled connection, or a [`postgres::Transaction`]
+    /// (or `&mut` one) to commit along with other changes.
+    pub fn from_client(client: C, config: Config) -> Migrator<C> {
+        let db = Db::from_client(client, &config);
+        Migrator {
+            source: Box::new(Directory::new(&config.migrate_dir)),
+            config,
+            db,
+        }
+    }
+
+    /// Reads migrations from `source` instead of the migrations directory.
+    pub fn with_source(mut self, source: impl MigrationSource + Send + Sync + 'static) -> Self {
+        self.source = Box::new(source);
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The underlying connection, for lower level operations.
+    pub fn db(&mut self) -> &mut Db<C> {
+        &mut self.db
+    }
+
+    /// The tenant schemas to migrate, if the config has any.
+    pub fn schemas(&mut self) -> Result<Vec<String>> {
+        match &self.config.tenants {
+            Some(tenants) => self.db.schemas(tenants),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Switches to a tenant schema: later operations use its bookkeeping
+    /// tables, and `${schema}` in templates refers to it.
+    pub fn set_schema(&mut self, schema: &str) -> Result<()> {
+        self.db.set_schema(schema)?;
+        self.config = self.config.for_schema(schema);
+        Ok(())
+    }
+
+    /// The state of every migration, in the order they're applied.
+    pub fn status(&mut self) -> Result<Vec<ApplicationState>> {
+        self.db.create_migrations_table()?;
+        get_migration_state(&mut self.db, &self.config, self.source.as_ref())
+    }
+
+    /// Applies pending migrations, then re-applies changed repeatable ones.
+    pub fn up(&mut self) -> Result<UpReport> {
+        let application_state = self.status()?;
+        let to_run = to_apply(&application_state);
+        let mut report = UpReport::default();
+        if to_run.is_empty() {
+            return Ok(report);
+        }
+        self.run_batch(Section::Up, &to_run, |db, definition| {
+            debug!("{}", definition.up_sql);
+            if is_changed(&application_state, definition) {
+                info!("reapplying {}", definition.name);
+                db.reapply(definition)?;
+                report.reapplied.push(definition.name.clone());
+            } else {
+                info!("applying {}", definition.name);
+                db.run(definition)?;
+                report.applied.push(definition.name.clone());
+            }
+            Ok(())
+        })?;
+        Ok(report)
+    }
+
+    /// Rolls back a single versioned migration.
+    pub fn down(&mut self, options: &DownOptions) -> Result<DownReport> {
+        if options.recover && options.ignore_changed {
+            return Err(Error::ConflictingRollbackOptions);
+        }
+        let application_state = self.status()?;
+        let Some(rollback) = to_revert(&application_state, options)? else {
+            return Ok(DownReport::default());
+        };
+        self.run_batch(Section::Down, &[rollback], |db, migration| {
+            debug!("{}", migration.down_sql);
+            info!("reverting {}", migration.name);
+            db.rollback_migration(migration)
+        })?;
+        Ok(DownReport {
+            reverted: Some(rollback.name.clone()),
+        })
+    }
+
+    /// Rolls back a versioned migration like [`down`](Self::down), then
+    /// applies its file again, e.g. after editing it in development. With
+    /// `recover`, a changed migration is rolled back with its stored sql and
+    /// re-applied with its file's.
+    pub fn redo(&mut self, options: &DownOptions) -> Result<RedoReport> {
+        if options.recover && options.ignore_changed {
+            return Err(Error::ConflictingRollbackOptions);
+        }
+        let application_state = self.status()?;
+        let Some(rollback) = to_redo(&application_state, options)? else {
+            return Ok(RedoReport::default());
+        };
+        let name = rollback.name.clone();
+        self.run_batch(Section::Down, &[rollback], |db, migration| {
+            debug!("{}", migration.down_sql);
+            info!("reverting {}", migration.name);
+            db.rollback_migration(migration)
+        })?;
+        let application_state = self.status()?;
+        let definition = to_reapply(&application_state, &name)?;
+        self.run_batch(Section::Up, &[definition], |db, definition| {
+            debug!("{}", definition.up_sql);
+            info!("applying {}", definition.name);
+            db.run(definition)?;
+            Ok(())
+        })?;
+        Ok(RedoReport { redone: Some(name) })
+    }
+
+    /// The migration [`down`](Self::down) would roll back, without rolling
+    /// it back.
+    pub fn plan_down(&mut self, options: &DownOptions) -> Result<Option<String>> {
+        if options.recover && options.ignore_changed {
+            return Err(Error::ConflictingRollbackOptions);
+        }
+        let application_state = self.status()?;
+        Ok(to_revert(&application_state, options)?.map(|m| m.name.clone()))
+    }
+
+    /// The migrations [`repair`](Self::repair) would repair.
+    pub fn plan_repair(&mut self, name: Option<&str>) -> Result<Vec<String>> {
+        let application_state = self.status()?;
+        Ok(names(to_repair(&application_state, name)?))
+    }
+
+    /// The migrations [`baseline`](Self::baseline) would mark as applied.
+    pub fn plan_baseline(&mut self, name: &str) -> Result<Vec<String>> {
+        let application_state = self.status()?;
+        Ok(names(to_baseline(&application_state, name)?))
+    }
+
+    /// Updates the stored sql of changed migrations (or only `name`) to
+    /// match their files, without running anything. Returns the names of
+    /// the migrations repaired.
+    pub fn repair(&mut self, name: Option<&str>) -> Result<Vec<String>> {
+        let application_state = self.status()?;
+        let mut repaired = Vec::new();
+        for definition in to_repair(&application_state, name)? {
+            info!("repairing {}", definition.name);
+            self.db.repair(definition)?;
+            repaired.push(definition.name.clone());
+        }
+        Ok(repaired)
+    }
+
+    /// Marks pending versioned migrations up to and including `name` as
+    /// applied, without running them. Returns their names.
+    pub fn baseline(&mut self, name: &str) -> Result<Vec<String>> {
+        let application_state = self.status()?;
+        let mut baselined = Vec::new();
+        for definition in to_baseline(&application_state, name)? {
+            info!("baselining {}", definition.name);
+            self.db.baseline(definition)?;
+            baselined.push(definition.name.clone());
+        }
+        Ok(baselined)
+    }
+
+    /// Marks the pending migrations that another tool recorded as applied,
+    /// in its own table, as applied in fly's.
+    pub fn import(&mut self, tool: Tool) -> Result<ImportReport> {
+        let versions = self.db.imported_versions(tool)?;
+        let application_state = self.status()?;
+        let (to_import, unmatched) = to_import(&application_state, tool, &versions);
+        let mut imported = Vec::new();
+        for definition in to_import {
+            info!("importing {} from {}", definition.name, tool);
+            self.db.baseline(definition)?;
+            imported.push(definition.name.clone());
+        }
+        Ok(ImportReport {
+            imported,
+            unmatched,
+        })
+    }
+
+    /// Recorded history events, oldest first, optionally only those of one
+    /// migration.
+    pub fn history(&mut self, name: Option<&str>) -> Result<Vec<HistoryEntry>> {
+        self.db.create_migrations_table()?;
+        self.db.history(name)
+    }
+
+    // Runs `f` on each migration, surrounded by the configured hooks.
+    fn run_batch(
+        &mut self,
+        section: Section,
+        migrations: &[&Migration],
+        mut f: impl FnMut(&mut Db<C>, &Migration) -> Result<()>,
+    ) -> Result<()> {
+        let hooks = Hooks::load(&self.config)?;
+        let db = &mut self.db;
+        let context = HookContext {
+            section,
+            migrations,
+            migration: None,
+        };
+        hooks.run(HookPoint::BeforeAll, db, &context)?;
+        for migration in migrations {
+            let context = HookContext {
+                migration: Some(migration),
+                ..context
+            };
+            hooks.run(HookPoint::BeforeEach, db, &context)?;
+            f(db, migration)?;
+            hooks.run(HookPoint::AfterEach, db, &context)?;
+        }
+        hooks.run(HookPoint::AfterAll, db, &context)?;
+        Ok(())
+    }
+}
+
+pub(crate) fn names(migrations: Vec<&Migration>) -> Vec<String> {
+    migrations.into_iter().map(|m| m.name.clone()).collect()
+}
+
+// The migrations `up` runs: pending migrations, then changed repeatable ones.
+pub(crate) fn to_apply(application_state: &[ApplicationState]) -> Vec<&Migration> {
+    application_state
+        .iter()
+        .filter_map(|application| match application {
+            ApplicationState::Pending { definition } => Some(definition),
+            ApplicationState::Changed {
+                definition,
+                application: _,
+            } if definition.is_repeatable() => Some(definition),
+            _ => None,
+        })
+        .collect()
+}
+
+// Whether `definition` was applied before, and so is re-applied by `up`.
+pub(crate) fn is_changed(application_state: &[ApplicationState], definition: &Migration) -> bool {
+    application_state
+        .iter()
+        .any(|a| a.is_changed() && a.name() == definition.name)
+}
+
+// The migration `down` rolls back, and which version of it: the file's, or
+// with `recover`, the one stored in the database.
+pub(crate) fn to_revert<'a>(
+    application_state: &'a [ApplicationState],
+    options: &DownOptions,
+) -> Result<Option<&'a Migration>> {
+    let application = match &options.name {
+        Some(name) => Some(
+            application_state
+                .iter()
+                .find(|application| application.name() == name)
+                .ok_or_else(|| Error::MigrationNotFound { name: name.clone() })?,
+        ),
+        None => application_state
+            .iter()
+            .rfind(|application| !application.is_pending() && !application.is_repeatable()),
+    };
+    let Some(application) = application else {
+        return Ok(None);
+    };
+    if application.is_repeatable() {
+        return Err(Error::RollbackRepeatable {
+            name: application.name().to_string(),
+        });
+    }
+
+    match application {
+        ApplicationState::Applied {
+            definition,
+            application: _,
+        } => Ok(Some(definition)),
+        ApplicationState::Changed {
+            definition,
+            application,
+        } => {
+            if options.recover {
+                Ok(Some(&application.migration))
+            } else if options.ignore_changed {
+                Ok(Some(definition))
+            } else {
+                Err(Error::MigrationChanged {
+                    name: definition.name.clone(),
+                })
+            }
+        }
+        ApplicationState::Removed { application } => {
+            if options.recover {
+                Ok(Some(&application.migration))
+            } else {
+                Err(Error::MigrationRemoved {
+                    name: application.migration.name.clone(),
+                })
+            }
+        }
+        ApplicationState::Pending { definition } => Err(Error::RollbackPending {
+            name: definition.name.clone(),
+        }),
+    }
+}
+
+// The migration `redo` rolls back and applies again: the one `down` would
+// roll back, as long as its file still exists.
+pub(crate) fn to_redo<'a>(
+    application_state: &'a [ApplicationState],
+    options: &DownOptions,
+) -> Result<Option<&'a Migration>> {
+    let Some(rollback) = to_revert(application_state, options)? else {
+        return Ok(None);
+    };
+    if let Some(ApplicationState::Removed { .. }) =
+        application_state.iter().find(|a| a.name() == rollback.name)
+    {
+        return Err(Error::MigrationRemoved {
+            name: rollback.name.clone(),
+        });
+    }
+    Ok(Some(rollback))
+}
+
+// The migration `redo` applies again, pending once it's been rolled back.
+pub(crate) fn to_reapply<'a>(
+    application_state: &'a [ApplicationState],
+    name: &str,
+) -> Result<&'a Migration> {
+    application_state
+        .iter()
+        .find_map(|application| match application {
+            ApplicationState::Pending { definition } if definition.name == name => Some(definition),
+            _ => None,
+        })
+        .ok_or_else(|| Error::MigrationNotFound {
+            name: name.to_string(),
+        })
+}
@@ -1,8 +1,10 @@
-use crate::db::Db;
+use crate::config::Config;
+use crate::db::{Connection, Db};
 use crate::error::Result;
-use crate::file;
-use crate::migration::{Migration, MigrationWithMeta};
-use std::collections::HashMap;
+use crate::migration::{Migration, MigrationKind, MigrationWithMeta};
+use crate::source::{Directory, MigrationSource};
+use crate::template;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::path::Path;
 
@@ -41,6 +43,25 @@ impl ApplicationState {
         matches!(self, ApplicationState::Removed { .. })
     }
 
+    pub fn is_repeatable(&self) -> bool {
+        self.kind() == MigrationKind::Repeatable
+    }
+
+    pub fn kind(&self) -> MigrationKind {
+        match self {
+            ApplicationState::Pending { definition } => definition.kind,
+            ApplicationState::Applied {
+                definition,
+                application: _,
+            } => definition.kind,
+            ApplicationState::Changed {
+                definition,
+                application: _,
+            } => definition.kind,
+            ApplicationState::Removed { application } => application.migration.kind,
+        }
+    }
+
     pub fn name(&self) -> &str {
         match self {
             ApplicationState::Pending { definition } => &definition.name,
@@ -55,6 +76,26 @@ impl ApplicationState {
             ApplicationState::Removed { application } => &application.migration.name,
         }
     }
+
+    /// A one-word description of the state, e.g. `applied`.
+    pub fn short_status(&self) -> &'static str {
+        match self {
+            ApplicationState::Pending { .. } => "pending",
+            ApplicationState::Applied { .. } => "applied",
+            ApplicationState::Changed { .. } => "changed",
+            ApplicationState::Removed { .. } => "removed",
+        }
+    }
+
+    /// The database record of the migration, unless it is pending.
+    pub fn application(&self) -> Option<&MigrationWithMeta> {
+        match self {
+            ApplicationState::Pending { .. } => None,
+            ApplicationState::Applied { application, .. }
+            | ApplicationState::Changed { application, .. }
+            | ApplicationState::Removed { application } => Some(application),
+        }
+    }
 }
 
 impl Display for ApplicationState {
@@ -70,7 +111,13 @@ impl Display for ApplicationState {
             ApplicationState::Changed {
                 definition: file,
                 application: _,
-            } => write!(f, "{} ** CHANGED **", file.name),
+            } => {
+                if file.is_repeatable() {
+                    write!(f, "{} [changed]", file.name)
+                } else {
+                    write!(f, "{} ** CHANGED **", file.name)
+                }
+            }
             ApplicationState::Removed { application: db } => {
                 write!(f, "{} ** NO FILE **", db.migration.name)
             }
@@ -78,16 +125,44 @@ impl Display for ApplicationState {
     }
 }
 
-pub fn get_all_migration_state(
-    db: &mut Db,
+/// The state of each migration in `migrate_dir`, with the default config:
+/// no template variables and the relaxed naming policy. Use
+/// [`get_migration_state`] to plan with a [`Config`].
+pub fn get_all_migration_state<C: Connection>(
+    db: &mut Db<C>,
     migrate_dir: impl AsRef<Path>,
 ) -> Result<Vec<ApplicationState>> {
-    let definitions = file::list(migrate_dir.as_ref())?;
+    let config = Config::new(migrate_dir, "");
+    get_migration_state(db, &config, &Directory::new(&config.migrate_dir))
+}
+
+/// The state of each migration from `source`, with templates rendered and
+/// names checked as `config` says.
+pub fn get_migration_state<C: Connection>(
+    db: &mut Db<C>,
+    config: &Config,
+    source: &(impl MigrationSource + ?Sized),
+) -> Result<Vec<ApplicationState>> {
+    let definitions = definitions(config, source)?;
     let applications = db.list()?;
     Ok(get_all_migration_state_impl(definitions, applications))
 }
 
-fn get_all_migration_state_impl(
+// Reads migrations from `source`, rendering templates and checking names.
+pub(crate) fn definitions(
+    config: &Config,
+    source: &(impl MigrationSource + ?Sized),
+) -> Result<Vec<Migration>> {
+    let definitions = source
+        .migrations()?
+        .into_iter()
+        .map(|m| template::render_migration(m, &config.template_vars))
+        .collect::<Result<Vec<_>>>()?;
+    config.naming.check(&definitions)?;
+    Ok(definitions)
+}
+
+pub(crate) fn get_all_migration_state_impl(
     definitions: Vec<Migration>,
     applications: Vec<MigrationWithMeta>,
 ) -> Vec<ApplicationState> {
@@ -100,17 +175,24 @@ fn get_all_migration_state_impl(
         .map(|m| (m.migration.name.clone(), m))
         .collect::<HashMap<String, MigrationWithMeta>>();
 
+    // Versioned migrations come first, then repeatable migrations; each group
+    // is sorted by name.
     let mut all_names = definitions
         .values()
-        .map(|v| v.name.clone())
-        .chain(applications.values().map(|v| v.migration.name.clone()))
-        .collect::<Vec<String>>();
+        .map(|v| (v.kind, v.name.clone()))
+        .chain(
+            applications
+                .values()
+                .map(|v| (v.migration.kind, v.migration.name.clone())),
+        )
+        .collect::<Vec<(MigrationKind, String)>>();
     all_names.sort();
-    all_names.dedup();
+    let mut seen = HashSet::new();
+    all_names.retain(|(_, name)| seen.insert(name.clone()));
 
     all_names
         .iter()
-        .map(|name| {
+        .map(|(_, name)| {
             let definition = definitions.get(name);
             let application = applications.get(name);
 
@@ -122,7 +204,7 @@ fn get_all_migration_state_impl(
                     definition: definition.clone(),
                 },
                 (Some(definition), Some(application)) => {
-                    if application.migration == *definition {
+                    if application.migration.checksum() == definition.checksum() {
                         ApplicationState::Applied {
                             definition: definition.clone(),
                             application: application.clone(),
@@ -158,24 +240,14 @@ mod test {
     fn test_get_all_migration_state_pending() {
         let definition = build_migration("migration", "up", "down");
         let result = get_all_migration_state_impl(vec![definition.clone()], Vec::new());
-        assert_eq!(
-            result,
-            vec![ApplicationState::Pending {
-                definition: definition
-            }]
-        );
+        assert_eq!(result, vec![ApplicationState::Pending { definition }]);
     }
 
     #[test]
     fn test_get_all_migration_state_removed() {
         let application = build_migration_meta("migration", "up", "down");
         let result = get_all_migration_state_impl(Vec::new(), vec![application.clone()]);
-        assert_eq!(
-            result,
-            vec![ApplicationState::Removed {
-                application: application
-            }]
-        );
+        assert_eq!(result, vec![ApplicationState::Removed { application }]);
     }
 
     #[test]
@@ -187,8 +259,8 @@ mod test {
         assert_eq!(
             result,
             vec![ApplicationState::Applied {
-                definition: definition,
-                application: application
+                definition,
+                application
             }]
         );
     }
@@ -202,8 +274,8 @@ mod test {
         assert_eq!(
             result,
             vec![ApplicationState::Changed {
-                definition: definition,
-                application: application
+                definition,
+                application
             }]
         );
     }
@@ -217,8 +289,8 @@ mod test {
         assert_eq!(
             result,
             vec![ApplicationState::Changed {
-                definition: definition,
-                application: application
+                definition,
+                application
             }]
         );
     }
@@ -272,11 +344,50 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_get_all_migration_state_repeatable_after_versioned() {
+        let versioned = build_migration("2-migration", "up", "down");
+        let repeatable_a = build_repeatable("a-views.sql", "create view a");
+        let repeatable_b = build_repeatable("b-views.sql", "create view b");
+        let application_b = build_repeatable_meta("b-views.sql", "create view b-old");
+
+        let result = get_all_migration_state_impl(
+            vec![
+                repeatable_b.clone(),
+                versioned.clone(),
+                repeatable_a.clone(),
+            ],
+            vec![application_b.clone()],
+        );
+        assert_eq!(
+            result,
+            vec![
+                ApplicationState::Pending {
+                    definition: versioned
+                },
+                ApplicationState::Pending {
+                    definition: repeatable_a
+                },
+                ApplicationState::Changed {
+                    definition: repeatable_b,
+                    application: application_b
+                },
+            ]
+        );
+    }
+
     fn build_migration(name: &'static str, up: &'static str, down: &'static str) -> Migration {
-        Migration {
-            up_sql: up.to_string(),
-            down_sql: down.to_string(),
-            name: name.to_string(),
+        Migration::new(name, up, down)
+    }
+
+    fn build_repeatable(name: &'static str, up: &'static str) -> Migration {
+        Migration::repeatable(name, up)
+    }
+
+    fn build_repeatable_meta(name: &'static str, up: &'static str) -> MigrationWithMeta {
+        MigrationWithMeta {
+            migration: build_repeatable(name, up),
+            ..build_migration_meta(name, up, "")
         }
     }
 
@@ -291,6 +402,10 @@ mod test {
             meta: MigrationMeta {
                 id: 123,
                 created_at: now,
+                applied_by: None,
+                applied_host: None,
+                fly_version: None,
+                execution_time: None,
             },
             migration: build_migration(name, up, down),
         }
@@ -0,0 +1,171 @@
+//! Updating fly's bookkeeping without running migrations: repairing the
+//! stored sql of changed migrations, and baselining pending ones as applied,
+//! e.g. on a database created from a schema dump.
+
+use crate::error::{Error, Result};
+use crate::migration::Migration;
+use crate::planner::ApplicationState;
+
+// The changed migrations `repair` updates: all of them, or only `name`.
+pub(crate) fn to_repair<'a>(
+    application_state: &'a [ApplicationState],
+    name: Option<&str>,
+) -> Result<Vec<&'a Migration>> {
+    if let Some(name) = name {
+        match application_state.iter().find(|a| a.name() == name) {
+            Some(application) if application.is_changed() => {}
+            Some(_) => {
+                return Err(Error::NothingToRepair {
+                    name: name.to_string(),
+                })
+            }
+            None => {
+                return Err(Error::MigrationNotFound {
+                    name: name.to_string(),
+                })
+            }
+        }
+    }
+    Ok(application_state
+        .iter()
+        .filter_map(|application| match application {
+            ApplicationState::Changed { definition, .. }
+                if name.is_none_or(|name| name == definition.name) =>
+            {
+                Some(definition)
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+// The pending versioned migrations `baseline` marks as applied, up to and
+// including `name`.
+pub(crate) fn to_baseline<'a>(
+    application_state: &'a [ApplicationState],
+    name: &str,
+) -> Result<Vec<&'a Migration>> {
+    match application_state.iter().find(|a| a.name() == name) {
+        Some(application) if !application.is_repeatable() => {}
+        Some(_) => {
+            return Err(Error::BaselineRepeatable {
+                name: name.to_string(),
+            })
+        }
+        None => {
+            return Err(Error::MigrationNotFound {
+                name: name.to_string(),
+            })
+        }
+    }
+    Ok(application_state
+        .iter()
+        .filter_map(|application| match application {
+            ApplicationState::Pending { definition }
+                if !definition.is_repeatable() && definition.name.as_str() <= name =>
+            {
+                Some(definition)
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::migration::{MigrationMeta, MigrationWithMeta};
+    use std::time::SystemTime;
+
+    fn pending(name: &str) -> ApplicationState {
+        ApplicationState::Pending {
+            definition: Migration::new(name, "select 1;", "select 1;"),
+        }
+    }
+
+    fn applied(name: &str, up: &str) -> MigrationWithMeta {
+        MigrationWithMeta {
+            migration: Migration::new(name, up, "select 1;"),
+            meta: MigrationMeta {
+                id: 1,
+                created_at: SystemTime::now(),
+                applied_by: None,
+                applied_host: None,
+                fly_version: None,
+                execution_time: None,
+            },
+        }
+    }
+
+    fn changed(name: &str) -> ApplicationState {
+        ApplicationState::Changed {
+            definition: Migration::new(name, "select 2;", "select 1;"),
+            application: applied(name, "select 1;"),
+        }
+    }
+
+    fn names(migrations: Vec<&Migration>) -> Vec<&str> {
+        migrations.into_iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_to_repair() {
+        let state = [
+            changed("1711200000-create-users.sql"),
+            ApplicationState::Applied {
+                definition: Migration::new("1711200001-create-posts.sql", "select 1;", "select 1;"),
+                application: applied("1711200001-create-posts.sql", "select 1;"),
+            },
+            changed("1711200002-create-tags.sql"),
+        ];
+        assert_eq!(
+            names(to_repair(&state, None).unwrap()),
+            ["1711200000-create-users.sql", "1711200002-create-tags.sql"]
+        );
+        assert_eq!(
+            names(to_repair(&state, Some("1711200002-create-tags.sql")).unwrap()),
+            ["1711200002-create-tags.sql"]
+        );
+        assert_eq!(
+            to_repair(&state, Some("1711200001-create-posts.sql"))
+                .err()
+                .unwrap()
+                .to_string(),
+            "1711200001-create-posts.sql has not changed, nothing to repair"
+        );
+        assert_eq!(
+            to_repair(&state, Some("1711200009-missing.sql"))
+                .err()
+                .unwrap()
+                .to_string(),
+            "couldn't find migration 1711200009-missing.sql"
+        );
+    }
+
+    #[test]
+    fn test_to_baseline() {
+        let state = [
+            pending("1711200000-create-users.sql"),
+            pending("1711200001-create-posts.sql"),
+            pending("1711200002-create-tags.sql"),
+            ApplicationState::Pending {
+                definition: Migration::repeatable("views.sql", "select 1;"),
+            },
+        ];
+        assert_eq!(
+            names(to_baseline(&state, "1711200001-create-posts.sql").unwrap()),
+            ["1711200000-create-users.sql", "1711200001-create-posts.sql"]
+        );
+        assert_eq!(
+            to_baseline(&state, "views.sql").err().unwrap().to_string(),
+            "can't baseline repeatable migration views.sql"
+        );
+        assert_eq!(
+            to_baseline(&state, "1711200009-missing.sql")
+                .err()
+                .unwrap()
+                .to_string(),
+            "couldn't find migration 1711200009-missing.sql"
+        );
+    }
+}
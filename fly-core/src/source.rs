@@ -0,0 +1,137 @@
+//! Where migrations are read from. `fly` reads `MIGRATE_DIR`, but a
+//! [`Migrator`](crate::migrator::Migrator) can plan from any source, e.g.
+//! migrations embedded with `fly::embed_migrations!` or built in a
+//! test.
+
+use crate::error::{Error, Result};
+use crate::file;
+use crate::migration::Migration;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Supplies the migration definitions to plan against.
+pub trait MigrationSource {
+    /// All migrations, sorted with [`Migration`]'s ordering. Names must be
+    /// unique.
+    fn migrations(&self) -> Result<Vec<Migration>>;
+}
+
+/// A migrations directory on disk, read each time migrations are listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directory {
+    pub path: PathBuf,
+}
+
+impl Directory {
+    pub fn new(path: impl AsRef<Path>) -> Directory {
+        Directory {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl MigrationSource for Directory {
+    fn migrations(&self) -> Result<Vec<Migration>> {
+        file::list(&self.path)
+    }
+}
+
+/// Migrations held in memory, e.g. embedded at compile time.
+impl MigrationSource for Vec<Migration> {
+    fn migrations(&self) -> Result<Vec<Migration>> {
+        let mut migrations = self.clone();
+        migrations.sort();
+        check_unique(&migrations)?;
+        Ok(migrations)
+    }
+}
+
+/// The migrations of several sources together, e.g. a shared directory and
+/// an application's own. A name found in more than one source is an error.
+#[derive(Default)]
+pub struct Merged {
+    sources: Vec<Box<dyn MigrationSource + Send + Sync>>,
+}
+
+impl Merged {
+    pub fn new() -> Merged {
+        Merged::default()
+    }
+
+    pub fn with(mut self, source: impl MigrationSource + Send + Sync + 'static) -> Merged {
+        self.sources.push(Box::new(source));
+        self
+    }
+}
+
+impl MigrationSource for Merged {
+    fn migrations(&self) -> Result<Vec<Migration>> {
+        let mut migrations = Vec::new();
+        for source in &self.sources {
+            migrations.extend(source.migrations()?);
+        }
+        migrations.sort();
+        check_unique(&migrations)?;
+        Ok(migrations)
+    }
+}
+
+fn check_unique(migrations: &[Migration]) -> Result<()> {
+    let mut names = HashSet::new();
+    for migration in migrations {
+        if !names.insert(&migration.name) {
+            return Err(Error::DuplicateSourceMigration {
+                name: migration.name.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn build_migration(name: &str) -> Migration {
+        Migration::new(name, "select 1;", "select 1;")
+    }
+
+    fn names(migrations: &[Migration]) -> Vec<&str> {
+        migrations.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_merged_sources() -> Result<()> {
+        let dir = tempdir()?;
+        fs::write(
+            dir.path().join("1711200001-bar.sql"),
+            "-- up\nselect 1;\n-- down\nselect 1;\n",
+        )?;
+        let merged = Merged::new().with(Directory::new(dir.path())).with(vec![
+            build_migration("1711200002-baz.sql"),
+            build_migration("1711200000-foo.sql"),
+        ]);
+        assert_eq!(
+            names(&merged.migrations()?),
+            [
+                "1711200000-foo.sql",
+                "1711200001-bar.sql",
+                "1711200002-baz.sql"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merged_duplicate() {
+        let merged = Merged::new()
+            .with(vec![build_migration("1711200000-foo.sql")])
+            .with(vec![build_migration("1711200000-foo.sql")]);
+        assert_eq!(
+            merged.migrations().err().unwrap().to_string(),
+            "migration 1711200000-foo.sql is in more than one source"
+        );
+    }
+}
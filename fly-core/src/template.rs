@@ -0,0 +1,181 @@
+//! Placeholder substitution for migrations marked with `-- fly:template`.
+//! `${name}` is replaced with the value of the variable `name`, and `$${`
+//! is an escaped, literal `${`. Placeholders in comments are left alone, so
+//! commenting out a line doesn't make its placeholders undefined. Those in
+//! string literals and dollar-quoted bodies are substituted, as that's where
+//! values such as passwords and schemas in function bodies go.
+
+use crate::error::{Error, Result};
+use crate::migration::{Migration, Section};
+use crate::sql::{self, SegmentKind};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Prefix of the environment variables holding template variables, e.g.
+/// `MIGRATE_VAR_APP_ROLE` defines `app_role`.
+pub static VAR_PREFIX: &str = "MIGRATE_VAR_";
+
+/// Template variables defined in the environment variables `env_vars`.
+pub fn vars_from_env(env_vars: &HashMap<String, String>) -> HashMap<String, String> {
+    env_vars
+        .iter()
+        .filter_map(|(key, value)| {
+            Some((key.strip_prefix(VAR_PREFIX)?.to_lowercase(), value.clone()))
+        })
+        .collect()
+}
+
+/// Substitutes variables into a migration's up and down sql, if it is a
+/// template. Other migrations are returned unchanged.
+pub fn render_migration(migration: Migration, vars: &HashMap<String, String>) -> Result<Migration> {
+    if !migration.template {
+        return Ok(migration);
+    }
+    let up_sql = render(&migration, Section::Up, vars)?;
+    let down_sql = render(&migration, Section::Down, vars)?;
+    Ok(Migration {
+        up_sql,
+        down_sql,
+        ..migration
+    })
+}
+
+fn render(
+    migration: &Migration,
+    section: Section,
+    vars: &HashMap<String, String>,
+) -> Result<String> {
+    let sql = migration.sql(section);
+    let mut rendered = String::with_capacity(sql.len());
+    for segment in sql::segments(sql) {
+        if segment.kind == SegmentKind::Comment {
+            rendered.push_str(&sql[segment.range]);
+        } else {
+            substitute(migration, section, vars, segment.range, &mut rendered)?;
+        }
+    }
+    Ok(rendered)
+}
+
+// Substitutes placeholders in the `range` of the section's sql, appending the
+// result to `rendered`.
+fn substitute(
+    migration: &Migration,
+    section: Section,
+    vars: &HashMap<String, String>,
+    range: Range<usize>,
+    rendered: &mut String,
+) -> Result<()> {
+    let sql = migration.sql(section);
+    let mut rest = range.start;
+    while let Some(found) = sql[rest..range.end].find('$') {
+        let at = rest + found;
+        rendered.push_str(&sql[rest..at]);
+        let after = &sql[at..range.end];
+        if after.starts_with("$${") {
+            rendered.push_str("${");
+            rest = at + 3;
+        } else if let Some(name) = placeholder(after) {
+            let value = vars
+                .get(name)
+                .ok_or_else(|| Error::UndefinedTemplateVariable {
+                    name: migration.name.clone(),
+                    variable: name.to_string(),
+                    line: migration.first_line(section) + sql::line_at(sql, at) - 1,
+                })?;
+            rendered.push_str(value);
+            rest = at + name.len() + 3;
+        } else {
+            rendered.push('$');
+            rest = at + 1;
+        }
+    }
+    rendered.push_str(&sql[rest..range.end]);
+    Ok(())
+}
+
+// The variable name of a `${name}` placeholder at the start of `s`.
+fn placeholder(s: &str) -> Option<&str> {
+    let inner = s.strip_prefix("${")?;
+    let name = &inner[..inner.find('}')?];
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::migration::SourceLines;
+
+    fn build_template(up: &str, down: &str) -> Migration {
+        Migration {
+            lines: SourceLines { up: 3, down: 6 },
+            template: true,
+            ..Migration::new("1711200000-foo.sql", up, down)
+        }
+    }
+
+    fn vars() -> HashMap<String, String> {
+        HashMap::from([
+            ("schema".to_string(), "tenant_1".to_string()),
+            ("app_role".to_string(), "app".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_render_migration() -> Result<()> {
+        let migration = build_template(
+            "create table ${schema}.users (id int);\ngrant select on ${schema}.users to ${app_role};",
+            "select '$${schema}', $1, $$ $$;\ndrop table ${schema}.users;",
+        );
+        let rendered = render_migration(migration, &vars())?;
+        assert_eq!(
+            rendered.up_sql,
+            "create table tenant_1.users (id int);\ngrant select on tenant_1.users to app;"
+        );
+        assert_eq!(
+            rendered.down_sql,
+            "select '${schema}', $1, $$ $$;\ndrop table tenant_1.users;"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_undefined_variable() {
+        let migration = build_template("select 1;", "select 1;\ndrop role ${admin_role};");
+        assert_eq!(
+            render_migration(migration, &vars())
+                .err()
+                .unwrap()
+                .to_string(),
+            "undefined template variable admin_role in 1711200000-foo.sql at line 7"
+        );
+    }
+
+    #[test]
+    fn test_render_skips_comments() -> Result<()> {
+        let migration = build_template(
+            "-- grant select on users to ${admin_role};\ncreate table ${schema}.users (id int); /* ${admin_role} */",
+            "",
+        );
+        assert_eq!(
+            render_migration(migration, &vars())?.up_sql,
+            "-- grant select on users to ${admin_role};\ncreate table tenant_1.users (id int); /* ${admin_role} */"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_leaves_other_migrations_alone() -> Result<()> {
+        let migration = Migration {
+            template: false,
+            ..build_template("select '${schema}';", "")
+        };
+        assert_eq!(render_migration(migration.clone(), &vars())?, migration);
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+#![cfg(feature = "async")]
+
+use anyhow::Result;
+use std::fs;
+use tempfile::tempdir;
+
+mod common;
+
+#[test]
+fn test_async_migrator() -> Result<()> {
+    use fly::async_migrator::AsyncMigrator;
+    use fly::config::Config;
+    use fly::migrator::DownOptions;
+
+    let migrate_dir = tempdir()?.into_path();
+    let database = common::TestDatabase::new()?;
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    fs::write(
+        migrate_dir.join("1711200001-create-posts.sql"),
+        "-- up\ncreate table posts (id int);\n-- down\ndrop table posts;\n",
+    )?;
+
+    let config = Config::new(&migrate_dir, database.connection_string());
+    // The runtime, and with it the connection, must be gone before the
+    // database is dropped.
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let mut migrator = AsyncMigrator::connect(config).await?;
+        // Migrating must work from a spawned task.
+        let (mut migrator, report) = tokio::spawn(async move {
+            let report = migrator.up().await;
+            (migrator, report)
+        })
+        .await?;
+        assert_eq!(
+            report?.applied,
+            ["1711200000-create-users.sql", "1711200001-create-posts.sql"]
+        );
+        assert!(migrator.up().await?.is_empty());
+
+        let report = migrator.down(&DownOptions::default()).await?;
+        assert_eq!(
+            report.reverted.as_deref(),
+            Some("1711200001-create-posts.sql")
+        );
+        assert_eq!(
+            migrator
+                .status()
+                .await?
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "1711200000-create-users.sql [applied]",
+                "1711200001-create-posts.sql [pending]"
+            ]
+        );
+        assert_eq!(migrator.history(None).await?.len(), 3);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_async_migrator_redo_import_and_plans() -> Result<()> {
+    use fly::async_migrator::AsyncMigrator;
+    use fly::config::Config;
+    use fly::import::Tool;
+    use fly::migrator::DownOptions;
+
+    let migrate_dir = tempdir()?.into_path();
+    let database = common::TestDatabase::new()?;
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    fs::write(
+        migrate_dir.join("1711200001-create-posts.sql"),
+        "-- up\ncreate table posts (id int);\n-- down\ndrop table posts;\n",
+    )?;
+    database.connect()?.batch_execute(
+        "create table schema_migrations (version text primary key);
+         insert into schema_migrations values ('1711200000');
+         create table users (id int);",
+    )?;
+
+    let config = Config::new(&migrate_dir, database.connection_string());
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let mut migrator = AsyncMigrator::connect(config).await?;
+        assert_eq!(
+            migrator
+                .plan_baseline("1711200000-create-users.sql")
+                .await?,
+            ["1711200000-create-users.sql"]
+        );
+        let report = migrator.import(Tool::Rails).await?;
+        assert_eq!(report.imported, ["1711200000-create-users.sql"]);
+        migrator.up().await?;
+
+        assert_eq!(
+            migrator
+                .plan_down(&DownOptions::default())
+                .await?
+                .as_deref(),
+            Some("1711200001-create-posts.sql")
+        );
+        assert!(migrator.plan_repair(None).await?.is_empty());
+        let report = migrator.redo(&DownOptions::default()).await?;
+        assert_eq!(
+            report.redone.as_deref(),
+            Some("1711200001-create-posts.sql")
+        );
+        assert!(migrator.status().await?.iter().all(|s| s.is_applied()));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_async_migrator_builder() -> Result<()> {
+    use fly::async_migrator::AsyncMigrator;
+    use fly::migration::Migration;
+
+    let database = common::TestDatabase::new()?;
+    let migrations = vec![Migration::new(
+        "1711200000-create-users.sql",
+        "create table users (id int);",
+        "drop table users;",
+    )];
+
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let mut migrator = AsyncMigrator::builder("/nonexistent")
+            .connection_string(database.connection_string())
+            .migrations(migrations)
+            .build()
+            .await?;
+        assert_eq!(
+            migrator.up().await?.applied,
+            ["1711200000-create-users.sql"]
+        );
+        Ok::<_, anyhow::Error>(())
+    })?;
+    database.connect()?.execute("select id from users", &[])?;
+
+    Ok(())
+}
+
+#[cfg(feature = "deadpool")]
+#[test]
+fn test_async_migrator_with_deadpool() -> Result<()> {
+    use deadpool_postgres::{Manager, Pool};
+    use fly::async_migrator::AsyncMigrator;
+    use fly::config::Config;
+
+    let migrate_dir = tempdir()?.into_path();
+    let database = common::TestDatabase::new()?;
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    let manager = Manager::new(database.connection_string().parse()?, postgres::NoTls);
+    let pool = Pool::builder(manager).max_size(1).build()?;
+
+    let config = Config::new(&migrate_dir, "");
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let mut migrator = AsyncMigrator::from_client(pool.get().await?, config);
+        assert_eq!(
+            migrator.up().await?.applied,
+            ["1711200000-create-users.sql"]
+        );
+        Ok(())
+    })
+}
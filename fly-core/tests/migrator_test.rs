@@ -0,0 +1,232 @@
+use anyhow::Result;
+use std::fs;
+use tempfile::tempdir;
+
+mod common;
+
+#[test]
+fn test_migrator_api() -> Result<()> {
+    use fly::migrator::{DownOptions, Migrator, UpReport};
+
+    let migrate_dir = tempdir()?.into_path();
+    let database = common::TestDatabase::new()?;
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    fs::write(
+        migrate_dir.join("1711200001-create-posts.sql"),
+        "-- up\ncreate table ${schema}posts (id int);\n-- down\ndrop table posts;\n-- fly:template\n",
+    )?;
+
+    let mut migrator = Migrator::builder(&migrate_dir)
+        .client(database.connect()?)
+        .template_var("schema", "public.")
+        .build()?;
+    assert_eq!(
+        migrator.up()?,
+        UpReport {
+            applied: vec![
+                "1711200000-create-users.sql".to_string(),
+                "1711200001-create-posts.sql".to_string()
+            ],
+            reapplied: vec![],
+        }
+    );
+    assert!(migrator.up()?.is_empty());
+    assert!(migrator.status()?.iter().all(|s| s.is_applied()));
+
+    let report = migrator.down(&DownOptions::default())?;
+    assert_eq!(
+        report.reverted.as_deref(),
+        Some("1711200001-create-posts.sql")
+    );
+    let err = migrator
+        .down(&DownOptions {
+            name: Some("1711200009-missing.sql".to_string()),
+            ..DownOptions::default()
+        })
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "couldn't find migration 1711200009-missing.sql"
+    );
+    assert_eq!(migrator.history(None)?.len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_migrator_with_embedded_migrations() -> Result<()> {
+    use fly::migration::Migration;
+    use fly::migrator::Migrator;
+
+    let database = common::TestDatabase::new()?;
+    let migrations = vec![Migration::new(
+        "1711200000-create-users.sql",
+        "create table users (id int);",
+        "drop table users;",
+    )];
+
+    let mut migrator = Migrator::builder("/nonexistent")
+        .client(database.connect()?)
+        .migrations(migrations)
+        .build()?;
+    assert_eq!(migrator.up()?.applied, ["1711200000-create-users.sql"]);
+    database.connect()?.execute("select id from users", &[])?;
+
+    Ok(())
+}
+
+#[test]
+fn test_get_all_migration_state() -> Result<()> {
+    use fly::config::Config;
+    use fly::db::Db;
+    use fly::planner::get_all_migration_state;
+
+    let migrate_dir = tempdir()?.into_path();
+    let database = common::TestDatabase::new()?;
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+
+    let mut db = Db::from_client(database.connect()?, &Config::new(&migrate_dir, ""));
+    db.create_migrations_table()?;
+    let states = get_all_migration_state(&mut db, &migrate_dir)?;
+    assert_eq!(states.len(), 1);
+    assert!(states[0].is_pending());
+
+    Ok(())
+}
+
+#[test]
+fn test_migrator_deferred_constraint_fails_migration() -> Result<()> {
+    use fly::error::Error;
+    use fly::migration::Migration;
+    use fly::migrator::Migrator;
+
+    let database = common::TestDatabase::new()?;
+    let migrations = vec![Migration::new(
+        "1711200000-create-posts.sql",
+        "create table posts (id int primary key, parent int references posts deferrable initially deferred);
+insert into posts values (1, 2);",
+        "drop table posts;",
+    )];
+
+    let mut migrator = Migrator::builder("/nonexistent")
+        .client(database.connect()?)
+        .migrations(migrations)
+        .build()?;
+    let err = migrator.up().err().unwrap();
+    assert!(
+        matches!(&err, Error::MigrationCommitFailed { name, .. } if name == "1711200000-create-posts.sql"),
+        "{err:?}"
+    );
+    assert!(migrator.status()?.iter().all(|s| s.is_pending()));
+
+    Ok(())
+}
+
+#[test]
+fn test_migrator_in_transaction() -> Result<()> {
+    use fly::config::Config;
+    use fly::migrator::Migrator;
+
+    let migrate_dir = tempdir()?.into_path();
+    let database = common::TestDatabase::new()?;
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    let config = Config::new(&migrate_dir, "");
+
+    let mut client = database.connect()?;
+    let mut transaction = client.transaction()?;
+    let mut migrator = Migrator::from_client(&mut transaction, config.clone());
+    assert_eq!(migrator.up()?.applied, ["1711200000-create-users.sql"]);
+    transaction.rollback()?;
+
+    // Nothing is kept unless the caller commits.
+    let mut migrator = Migrator::from_client(&mut client, config);
+    assert!(migrator.status()?.iter().all(|s| s.is_pending()));
+
+    Ok(())
+}
+
+#[test]
+fn test_migrator_in_transaction_restores_timeouts() -> Result<()> {
+    use fly::config::Config;
+    use fly::migrator::Migrator;
+
+    let migrate_dir = tempdir()?.into_path();
+    let database = common::TestDatabase::new()?;
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- fly:statement-timeout 1s
+-- up
+create table users (timeout text);
+insert into users values (current_setting('statement_timeout'));
+-- down
+drop table users;
+",
+    )?;
+    fs::write(
+        migrate_dir.join("1711200001-create-posts.sql"),
+        "-- fly:lock-timeout 2s
+-- up
+create table posts (statement_timeout text, lock_timeout text);
+insert into posts values (current_setting('statement_timeout'), current_setting('lock_timeout'));
+-- down
+drop table posts;
+",
+    )?;
+    let config = Config::new(&migrate_dir, "");
+
+    let mut client = database.connect()?;
+    let mut transaction = client.transaction()?;
+    transaction.batch_execute("set local statement_timeout = '5min'")?;
+    let mut migrator = Migrator::from_client(&mut transaction, config);
+    migrator.up()?;
+
+    // Each migration's timeouts end with its savepoint.
+    let users = transaction.query_one("select timeout from users", &[])?;
+    assert_eq!(users.get::<_, &str>(0), "1s");
+    let posts = transaction.query_one("select * from posts", &[])?;
+    assert_eq!(posts.get::<_, &str>(0), "5min");
+    assert_eq!(posts.get::<_, &str>(1), "2s");
+    let settings = transaction.query_one(
+        "select current_setting('statement_timeout'), current_setting('lock_timeout')",
+        &[],
+    )?;
+    assert_eq!(settings.get::<_, &str>(0), "5min");
+    assert_eq!(settings.get::<_, &str>(1), "0");
+    transaction.rollback()?;
+
+    Ok(())
+}
+
+#[cfg(feature = "r2d2")]
+#[test]
+fn test_migrator_with_r2d2_pool() -> Result<()> {
+    use fly::config::Config;
+    use fly::migrator::Migrator;
+    use r2d2_postgres::PostgresConnectionManager;
+
+    let migrate_dir = tempdir()?.into_path();
+    let database = common::TestDatabase::new()?;
+    fs::write(
+        migrate_dir.join("1711200000-create-users.sql"),
+        "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+    )?;
+    let manager =
+        PostgresConnectionManager::new(database.connection_string().parse()?, postgres::NoTls);
+    let pool = r2d2::Pool::builder().max_size(1).build(manager)?;
+
+    let config = Config::new(&migrate_dir, "");
+    let mut migrator = Migrator::from_client(pool.get()?, config);
+    assert_eq!(migrator.up()?.applied, ["1711200000-create-users.sql"]);
+
+    Ok(())
+}
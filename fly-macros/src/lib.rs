@@ -0,0 +1,129 @@
+//! Compile-time embedding of fly migrations, for deploying a service as a
+//! single binary.
+
+use fly_parse::file;
+use fly_parse::migration::{Migration, MigrationKind};
+use fly_parse::naming::NamingPolicy;
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use syn::{parse_macro_input, LitStr};
+
+/// Reads the migrations in a directory, relative to the crate's
+/// `Cargo.toml`, at compile time, and expands to a
+/// `Vec<fly::migration::Migration>` of them:
+///
+/// ```ignore
+/// let migrations = fly::embed_migrations!("migrations");
+/// let mut migrator = fly::migrator::Migrator::builder("migrations")
+///     .connection_string(url)
+///     .migrations(migrations)
+///     .build()?;
+/// ```
+///
+/// Files are parsed with the same rules as `fly up`, and migrations that
+/// `fly up` would reject under the default, relaxed naming policy fail the
+/// build, e.g. a malformed file or two migrations sharing a version. A
+/// stricter policy passed to the builder is only checked at runtime, and
+/// template placeholders are left for the migrator to substitute.
+#[proc_macro]
+pub fn embed_migrations(input: TokenStream) -> TokenStream {
+    let dir = parse_macro_input!(input as LitStr);
+    match embed(&dir) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => syn::Error::new(dir.span(), e).to_compile_error().into(),
+    }
+}
+
+fn embed(dir: &LitStr) -> Result<TokenStream2, String> {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").map_err(|e| e.to_string())?;
+    let migrate_dir = Path::new(&manifest_dir).join(dir.value());
+    if !migrate_dir.is_dir() {
+        return Err(format!(
+            "migration directory {} not found",
+            migrate_dir.display()
+        ));
+    }
+    let migrations = file::list(&migrate_dir).map_err(|e| e.to_string())?;
+    NamingPolicy::relaxed()
+        .check(&migrations)
+        .map_err(|e| e.to_string())?;
+    let migrations = migrations.iter().map(migration_tokens);
+
+    // Including each file makes cargo rebuild when a migration changes.
+    let mut files = Vec::new();
+    collect_files(&migrate_dir, &mut files).map_err(|e| e.to_string())?;
+    let files = files
+        .iter()
+        .map(|path| LitStr::new(&path.to_string_lossy(), Span::call_site()));
+
+    Ok(quote! {
+        {
+            #(const _: &[u8] = include_bytes!(#files);)*
+            ::std::vec![#(#migrations),*]
+        }
+    })
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(())
+}
+
+fn migration_tokens(migration: &Migration) -> TokenStream2 {
+    let Migration {
+        up_sql,
+        down_sql,
+        name,
+        kind,
+        lines,
+        timeouts,
+        template,
+        lint_ignore,
+    } = migration;
+    let kind = match kind {
+        MigrationKind::Versioned => quote!(::fly::migration::MigrationKind::Versioned),
+        MigrationKind::Repeatable => quote!(::fly::migration::MigrationKind::Repeatable),
+    };
+    let (up_line, down_line) = (lines.up, lines.down);
+    let lock_timeout = duration_tokens(timeouts.lock_timeout);
+    let statement_timeout = duration_tokens(timeouts.statement_timeout);
+    quote! {
+        ::fly::migration::Migration {
+            up_sql: ::std::string::String::from(#up_sql),
+            down_sql: ::std::string::String::from(#down_sql),
+            name: ::std::string::String::from(#name),
+            kind: #kind,
+            lines: ::fly::migration::SourceLines {
+                up: #up_line,
+                down: #down_line,
+            },
+            timeouts: ::fly::migration::Timeouts {
+                lock_timeout: #lock_timeout,
+                statement_timeout: #statement_timeout,
+            },
+            template: #template,
+            lint_ignore: ::std::vec![#(::std::string::String::from(#lint_ignore)),*],
+        }
+    }
+}
+
+fn duration_tokens(duration: Option<Duration>) -> TokenStream2 {
+    match duration {
+        Some(duration) => {
+            let millis = duration.as_millis() as u64;
+            quote!(::std::option::Option::Some(::std::time::Duration::from_millis(#millis)))
+        }
+        None => quote!(::std::option::Option::None),
+    }
+}
@@ -0,0 +1,45 @@
+use fly::migration::{Migration, MigrationKind, Timeouts};
+use fly_macros::embed_migrations;
+use std::time::Duration;
+
+#[test]
+fn test_embeds_migrations() {
+    let migrations: Vec<Migration> = embed_migrations!("tests/migrations");
+    let expected = fly::file::list(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/migrations"))
+        .expect("reading migrations");
+    assert_eq!(migrations, expected);
+
+    let names = migrations
+        .iter()
+        .map(|m| m.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "1711200000-create-users.sql",
+            "1711200001-create-posts.sql",
+            "user_ids.sql"
+        ]
+    );
+    assert_eq!(migrations[2].kind, MigrationKind::Repeatable);
+    assert!(migrations[1].template);
+    assert_eq!(
+        migrations[1].timeouts,
+        Timeouts {
+            lock_timeout: Some(Duration::from_secs(5)),
+            statement_timeout: None,
+        }
+    );
+}
+
+#[test]
+fn test_reexported_from_fly() {
+    let migrations: Vec<Migration> = fly::embed_migrations!("tests/migrations");
+    assert_eq!(migrations, embed_migrations!("tests/migrations"));
+}
+
+#[test]
+fn test_rejects_invalid_migrations() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,4 @@
+-- up
+create table users (id int);
+-- down
+drop table users;
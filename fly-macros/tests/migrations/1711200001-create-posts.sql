@@ -0,0 +1,6 @@
+-- fly:lock-timeout 5s
+-- fly:template
+-- up
+create table ${schema}posts (id int);
+-- down
+drop table posts;
@@ -0,0 +1 @@
+create or replace view user_ids as select id from users;
@@ -0,0 +1,4 @@
+-- up
+create table posts (id int);
+-- down
+drop table posts;
@@ -0,0 +1,5 @@
+// trybuild builds this in target/tests/trybuild/fly-migrate-macros, which
+// the macro takes as the crate's directory.
+fn main() {
+    let _ = fly_macros::embed_migrations!("../../../../fly-macros/tests/ui/duplicate-version");
+}
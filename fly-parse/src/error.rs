@@ -0,0 +1,211 @@
+//! The errors returned by fly. [`Error`] is `Send + Sync + 'static`, so it
+//! can be sent across threads and converted into `anyhow::Error` or
+//! `Box<dyn std::error::Error + Send + Sync>`, and keeps the underlying
+//! postgres or io error as its [`source`](std::error::Error::source).
+
+use crate::migration::Section;
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// New variants may be added in minor releases, so matches need a wildcard
+/// arm.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Pg(#[from] postgres::Error),
+    #[error("couldn't connect to database")]
+    Connection(#[source] postgres::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("required environment variable {name} not set")]
+    MissingEnv { name: String },
+    #[error("environment variable {name} could not be parsed: {reason}")]
+    BadEnvFormat { name: String, reason: String },
+    #[error("no filename given")]
+    FilenameRequired,
+    #[error("filename must be utf-8 encoded")]
+    FilenameBadEncoding,
+    #[error("bad migration file format in {name}{}: {reason}", .line.map(|l| format!(" at line {}", l)).unwrap_or_default())]
+    MigrationFileFormatError {
+        reason: String,
+        name: String,
+        line: Option<usize>,
+    },
+    #[error("duplicate migration {name}: found at {} and {}", .first.display(), .second.display())]
+    DuplicateMigration {
+        name: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    #[error("invalid migration name {name}: {reason}")]
+    InvalidMigrationName { name: String, reason: String },
+    #[error("migration {name} is in more than one source")]
+    DuplicateSourceMigration { name: String },
+    #[error("migrations {first} and {second} share version {version}")]
+    DuplicateVersion {
+        version: String,
+        first: String,
+        second: String,
+    },
+    #[error(transparent)]
+    MigrationStatementFailed(Box<StatementFailure>),
+    /// The migration's sql violated a deferred constraint, which failed its
+    /// transaction's COMMIT after every statement had run.
+    #[error("migration {name} failed at commit: {message}")]
+    MigrationCommitFailed {
+        name: String,
+        message: String,
+        #[source]
+        source: postgres::Error,
+    },
+    /// Recording the migration in the migrations or history table failed,
+    /// or its transaction couldn't be started, set up or committed, so it
+    /// was rolled back.
+    #[error("couldn't record {name} in the bookkeeping tables")]
+    Bookkeeping {
+        name: String,
+        #[source]
+        source: postgres::Error,
+    },
+    #[error("expected 1 row in the migrations table for {name}, found {rows}")]
+    UnexpectedRowCount { name: String, rows: usize },
+    /// A lock timeout fired on the last attempt allowed by the retry policy.
+    #[error("{name} timed out waiting for a lock after {attempts} attempt(s)")]
+    LockTimeout {
+        name: String,
+        attempts: u32,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("unknown migration kind {kind}")]
+    UnknownMigrationKind { kind: String },
+    #[error("unknown history event {event}")]
+    UnknownHistoryEvent { event: String },
+    #[error("{hook} hook failed: command exited with {status}")]
+    HookFailed {
+        hook: String,
+        status: std::process::ExitStatus,
+    },
+    #[error("{hook} hook couldn't be started")]
+    HookNotStarted {
+        hook: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("couldn't find migration {name}")]
+    MigrationNotFound { name: String },
+    /// Drift: the migration's file no longer matches the sql it was applied
+    /// with.
+    #[error("{name} has changed since it was applied")]
+    MigrationChanged { name: String },
+    /// Drift: an applied migration's file is gone.
+    #[error("{name} was removed after it was applied")]
+    MigrationRemoved { name: String },
+    #[error("can't roll back a pending migration {name}")]
+    RollbackPending { name: String },
+    #[error("can't roll back repeatable migration {name}")]
+    RollbackRepeatable { name: String },
+    #[error("can't both recover and ignore changes when rolling back")]
+    ConflictingRollbackOptions,
+    #[error("{name} has not changed, nothing to repair")]
+    NothingToRepair { name: String },
+    #[error("can't baseline repeatable migration {name}")]
+    BaselineRepeatable { name: String },
+    #[error("unknown migration tool {tool}")]
+    UnknownImportTool { tool: String },
+    #[error("table {table} not found, nothing to import")]
+    ImportTableNotFound { table: String },
+    #[error("{} already exists", .path.display())]
+    MigrationFileExists { path: PathBuf },
+    #[error("schema {schema} does not exist")]
+    SchemaNotFound { schema: String },
+    #[error("undefined template variable {variable} in {name} at line {line}")]
+    UndefinedTemplateVariable {
+        name: String,
+        variable: String,
+        line: usize,
+    },
+}
+
+/// A statement of a migration that postgres rejected.
+#[derive(Error, Debug)]
+#[error(
+    "migration {name} failed at line {} (statement {index} of the {section} sql, lines {first_line}-{last_line}): {message}",
+    .error_line.unwrap_or(*.first_line)
+)]
+pub struct StatementFailure {
+    pub name: String,
+    pub section: Section,
+    /// Position of the statement in its section, counted from 1.
+    pub index: usize,
+    pub statement: String,
+    /// Lines of the statement in the migration file.
+    pub first_line: usize,
+    pub last_line: usize,
+    /// The line postgres reported the error at, if it gave a position.
+    pub error_line: Option<usize>,
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+    #[source]
+    pub source: postgres::Error,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error as _;
+    use std::io;
+
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+
+    #[test]
+    fn test_error_is_send_sync() {
+        assert_send_sync::<Error>();
+        assert_send_sync::<StatementFailure>();
+    }
+
+    #[test]
+    fn test_error_crosses_threads() {
+        let error = std::thread::spawn(|| Error::MigrationNotFound {
+            name: "1711200000-foo.sql".to_string(),
+        })
+        .join()
+        .unwrap();
+        let error = anyhow::Error::from(error);
+        assert_eq!(
+            error.to_string(),
+            "couldn't find migration 1711200000-foo.sql"
+        );
+    }
+
+    #[test]
+    fn test_source_chain() {
+        let error = Error::LockTimeout {
+            name: "1711200000-foo.sql".to_string(),
+            attempts: 1,
+            source: Box::new(Error::HookNotStarted {
+                hook: "before_all".to_string(),
+                source: io::Error::new(io::ErrorKind::NotFound, "sh not found"),
+            }),
+        };
+        let chain = anyhow::Error::from(error)
+            .chain()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chain,
+            [
+                "1711200000-foo.sql timed out waiting for a lock after 1 attempt(s)",
+                "before_all hook couldn't be started",
+                "sh not found",
+            ]
+        );
+
+        let error = Error::from(io::Error::new(io::ErrorKind::NotFound, "gone"));
+        assert!(error.source().is_none(), "io errors are transparent");
+    }
+}
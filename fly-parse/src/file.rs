@@ -0,0 +1,850 @@
+use crate::{
+    error::Error,
+    error::Result,
+    migration::{parse_duration, Migration, MigrationKind, SourceLines, Timeouts},
+    sql,
+};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Name of the subdirectory of `migrate_dir` holding repeatable migrations.
+pub static REPEATABLE_DIR: &str = "repeatable";
+
+/// Name of the subdirectory of `migrate_dir` holding sql hooks, which
+/// aren't migrations.
+pub static HOOKS_DIR: &str = "hooks";
+
+/// File names of the sections of a directory migration.
+pub static UP_FILE: &str = "up.sql";
+pub static DOWN_FILE: &str = "down.sql";
+
+/// Lists all migrations under `migrate_dir`, including those in nested
+/// subdirectories. Migrations are identified and ordered by their file (or
+/// directory) name alone, so subdirectories are purely organizational and
+/// two migrations with the same name anywhere in the tree are an error.
+pub fn list(migrate_dir: impl AsRef<Path>) -> Result<Vec<Migration>> {
+    let mut found = Vec::new();
+    collect(
+        migrate_dir.as_ref(),
+        MigrationKind::Versioned,
+        true,
+        &mut found,
+    )?;
+
+    let mut paths: HashMap<String, PathBuf> = HashMap::new();
+    let mut migrations = Vec::new();
+    for (path, migration) in found {
+        if let Some(first) = paths.get(&migration.name) {
+            return Err(Error::DuplicateMigration {
+                name: migration.name,
+                first: first.clone(),
+                second: path,
+            });
+        }
+        paths.insert(migration.name.clone(), path);
+        migrations.push(migration);
+    }
+    migrations.sort();
+    Ok(migrations)
+}
+
+// Recursively collects migrations of the given kind from `dir`. Entries are
+// visited in sorted order so that errors are reported deterministically.
+// Symlinked directories are not followed.
+fn collect(
+    dir: &Path,
+    kind: MigrationKind,
+    top_level: bool,
+    found: &mut Vec<(PathBuf, Migration)>,
+) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        if valid_migration_file_path(&path) {
+            let migration = match kind {
+                MigrationKind::Versioned => parse_migration_from_file(&path)?,
+                MigrationKind::Repeatable => parse_repeatable_migration_from_file(&path)?,
+            };
+            found.push((path, migration));
+        } else if valid_migration_dir_path(&path) {
+            // Otherwise its up.sql and down.sql would be read as two
+            // repeatable migrations.
+            if kind == MigrationKind::Repeatable {
+                return Err(Error::MigrationFileFormatError {
+                    reason: "repeatable migrations must be single .sql files".to_string(),
+                    name: file_name(&path)?,
+                    line: None,
+                });
+            }
+            let migration = parse_migration_from_dir(&path)?;
+            found.push((path, migration));
+        } else if top_level && entry.file_name() == HOOKS_DIR {
+            continue;
+        } else if entry.file_type()?.is_dir() && !is_hidden(&path) {
+            let kind = if top_level && entry.file_name() == REPEATABLE_DIR {
+                MigrationKind::Repeatable
+            } else {
+                kind
+            };
+            collect(&path, kind, false, found)?;
+        }
+    }
+    Ok(())
+}
+
+fn is_hidden(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .file_name()
+        .is_some_and(|f| f.to_string_lossy().starts_with("."))
+}
+
+// Check that a path is a file, ends in .sql, and does not start with a dot.
+fn valid_migration_file_path(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|f| f.to_string_lossy() == "sql")
+        && !is_hidden(path)
+}
+
+// Check that a path is a directory containing an up.sql or down.sql file,
+// and does not start with a dot. Whether both are present is checked when
+// parsing, so that a half-written migration is an error rather than ignored.
+fn valid_migration_dir_path(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    path.is_dir()
+        && (path.join(UP_FILE).is_file() || path.join(DOWN_FILE).is_file())
+        && !is_hidden(path)
+        && path.file_name().is_some_and(|f| f != REPEATABLE_DIR)
+}
+
+fn parse_migration_from_file(path: impl AsRef<Path>) -> Result<Migration> {
+    let name = file_name(&path)?;
+    let file = std::fs::File::open(&path)?;
+    parse_migration(name, file)
+}
+
+pub fn parse_repeatable_migration_from_file(path: impl AsRef<Path>) -> Result<Migration> {
+    let name = file_name(&path)?;
+    let file = std::fs::File::open(&path)?;
+    parse_repeatable_migration(name, file)
+}
+
+// A directory migration holds its up and down sql in separate files, so
+// they can be edited with regular SQL tooling. Any other files in the
+// directory (e.g. notes or a README) are ignored.
+fn parse_migration_from_dir(path: impl AsRef<Path>) -> Result<Migration> {
+    let path = path.as_ref();
+    let name = file_name(path)?;
+    let up_path = path.join(UP_FILE);
+    let down_path = path.join(DOWN_FILE);
+    if !(up_path.is_file() && down_path.is_file()) {
+        return Err(Error::MigrationFileFormatError {
+            reason: format!("directory must contain both {} and {}", UP_FILE, DOWN_FILE),
+            name,
+            line: None,
+        });
+    }
+    let up = std::fs::read_to_string(up_path)?;
+    let down = std::fs::read_to_string(down_path)?;
+    let Directives {
+        timeouts,
+        template,
+        lint_ignore,
+    } = directives(&name, &up)?;
+    Ok(Migration {
+        up_sql: up.trim().to_string(),
+        down_sql: down.trim().to_string(),
+        name,
+        kind: MigrationKind::Versioned,
+        lines: SourceLines {
+            up: first_content_line(&up),
+            down: first_content_line(&down),
+        },
+        timeouts,
+        template,
+        lint_ignore,
+    })
+}
+
+fn file_name(path: impl AsRef<Path>) -> Result<String> {
+    Ok(path
+        .as_ref()
+        .file_name()
+        .ok_or(Error::FilenameRequired)?
+        .to_str()
+        .ok_or(Error::FilenameBadEncoding)?
+        .to_string())
+}
+
+// Repeatable migrations have no up/down sections: the whole file is re-run
+// whenever it changes, so it should be written to be idempotent (e.g.
+// `CREATE OR REPLACE VIEW`).
+fn parse_repeatable_migration(name: String, mut reader: impl Read) -> Result<Migration> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    let up_sql = contents.trim().to_string();
+    if up_sql.is_empty() {
+        return Err(Error::MigrationFileFormatError {
+            reason: "repeatable migration is empty".to_string(),
+            name,
+            line: None,
+        });
+    }
+    let Directives {
+        timeouts,
+        template,
+        lint_ignore,
+    } = directives(&name, &contents)?;
+    Ok(Migration {
+        up_sql,
+        down_sql: String::new(),
+        name,
+        kind: MigrationKind::Repeatable,
+        lines: SourceLines {
+            up: first_content_line(&contents),
+            down: 1,
+        },
+        timeouts,
+        template,
+        lint_ignore,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Marker {
+    Up,
+    Down,
+}
+
+// Recognizes `-- up` and `-- down` section markers, ignoring case and
+// surrounding whitespace, e.g. `--UP` or `  -- Down  `.
+fn marker(line: &str) -> Option<Marker> {
+    let comment = line.trim().strip_prefix("--")?.trim();
+    if comment.eq_ignore_ascii_case("up") {
+        Some(Marker::Up)
+    } else if comment.eq_ignore_ascii_case("down") {
+        Some(Marker::Down)
+    } else {
+        None
+    }
+}
+
+// Splits a migration file into its up and down sections. Marker lines are
+// only recognized outside of string literals, dollar-quoted bodies and block
+// comments, so e.g. a function body containing `-- down` is left intact.
+// Line endings are normalized to `\n`.
+fn parse_migration(name: String, mut reader: impl Read) -> Result<Migration> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    let segments = sql::segments(&contents);
+    let error = |reason: &str, line: Option<usize>| Error::MigrationFileFormatError {
+        reason: reason.to_string(),
+        name: name.clone(),
+        line,
+    };
+
+    let mut up = String::new();
+    let mut down = String::new();
+    let mut up_line = None;
+    let mut down_line = None;
+    let mut section = None;
+    let mut offset = 0;
+    for (i, raw_line) in contents.split('\n').enumerate() {
+        let line_number = i + 1;
+        let line = raw_line.strip_suffix('\r').unwrap_or(raw_line);
+        let at = offset;
+        offset += raw_line.len() + 1;
+
+        let marker = if sql::starts_in_code(&segments, at) {
+            marker(line)
+        } else {
+            None
+        };
+        match marker {
+            Some(Marker::Up) => {
+                if up_line.is_some() {
+                    return Err(error("only one up migration allowed", Some(line_number)));
+                }
+                if down_line.is_some() {
+                    return Err(error("up migration must come first", Some(line_number)));
+                }
+                up_line = Some(line_number);
+                section = Some(Marker::Up);
+            }
+            Some(Marker::Down) => {
+                if down_line.is_some() {
+                    return Err(error("only one down migration allowed", Some(line_number)));
+                }
+                down_line = Some(line_number);
+                section = Some(Marker::Down);
+            }
+            None => {
+                let sql = match section {
+                    Some(Marker::Up) => &mut up,
+                    Some(Marker::Down) => &mut down,
+                    None => continue,
+                };
+                sql.push_str(line);
+                sql.push('\n');
+            }
+        }
+    }
+
+    let (Some(up_line), Some(down_line)) = (up_line, down_line) else {
+        return Err(error("both up and down migrations must be defined", None));
+    };
+    let Directives {
+        timeouts,
+        template,
+        lint_ignore,
+    } = directives(&name, &contents)?;
+    Ok(Migration {
+        up_sql: up.trim().to_string(),
+        down_sql: down.trim().to_string(),
+        name,
+        kind: MigrationKind::Versioned,
+        lines: SourceLines {
+            up: up_line + first_content_line(&up),
+            down: down_line + first_content_line(&down),
+        },
+        timeouts,
+        template,
+        lint_ignore,
+    })
+}
+
+// Settings read from `fly:` comment directives.
+#[derive(Debug, Default)]
+struct Directives {
+    timeouts: Timeouts,
+    template: bool,
+    lint_ignore: Vec<String>,
+}
+
+// Reads `-- fly:lock-timeout 5s`, `-- fly:statement-timeout 1min`,
+// `-- fly:template` and `-- fly:lint-ignore FLY001, FLY002` directives. They
+// may appear anywhere in the file outside of literals, conventionally above
+// `-- up`. Unknown `fly:` directives are ignored.
+fn directives(name: &str, contents: &str) -> Result<Directives> {
+    let segments = sql::segments(contents);
+    let mut directives = Directives::default();
+    let mut offset = 0;
+    for (i, line) in contents.split('\n').enumerate() {
+        let at = offset;
+        offset += line.len() + 1;
+        if !sql::starts_in_code(&segments, at) {
+            continue;
+        }
+        let Some(directive) = line
+            .trim()
+            .strip_prefix("--")
+            .and_then(|comment| comment.trim().strip_prefix("fly:"))
+        else {
+            continue;
+        };
+        let (key, value) = directive
+            .split_once(char::is_whitespace)
+            .unwrap_or((directive, ""));
+        let timeout = match key.trim() {
+            "template" => {
+                directives.template = true;
+                continue;
+            }
+            "lint-ignore" => {
+                directives.lint_ignore.extend(
+                    value
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|id| !id.is_empty())
+                        .map(|id| id.to_uppercase()),
+                );
+                continue;
+            }
+            "lock-timeout" => &mut directives.timeouts.lock_timeout,
+            "statement-timeout" => &mut directives.timeouts.statement_timeout,
+            _ => continue,
+        };
+        *timeout =
+            Some(
+                parse_duration(value.trim()).map_err(|_| Error::MigrationFileFormatError {
+                    reason: format!("invalid duration {:?} for fly:{}", value.trim(), key),
+                    name: name.to_string(),
+                    line: Some(i + 1),
+                })?,
+            );
+    }
+    Ok(directives)
+}
+
+// The line, counted from 1, on which `text` has its first non-blank line.
+fn first_content_line(text: &str) -> usize {
+    text.lines()
+        .take_while(|line| line.trim().is_empty())
+        .count()
+        + 1
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::io::Cursor;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_migration_empty_string() -> Result<()> {
+        let migration_str = "".to_string();
+        let result = parse_migration("foo".to_string(), Cursor::new(migration_str));
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "bad migration file format in foo: both up and down migrations must be defined"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_migration_just_up() -> Result<()> {
+        let migration_str = "
+-- up
+create table users (id int);
+"
+        .to_string();
+        let result = parse_migration("foo".to_string(), Cursor::new(migration_str));
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "bad migration file format in foo: both up and down migrations must be defined"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_migration_just_down() -> Result<()> {
+        let migration_str = "
+-- down
+drop table users;
+"
+        .to_string();
+        let result = parse_migration("foo".to_string(), Cursor::new(migration_str));
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "bad migration file format in foo: both up and down migrations must be defined"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_migration_multiple_ups() -> Result<()> {
+        let migration_str = "
+-- up
+create table users (id int);
+
+-- up
+alter table users add column is_active boolean default true;
+
+-- down
+drop table users;
+"
+        .to_string();
+        let result = parse_migration("foo".to_string(), Cursor::new(migration_str));
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "bad migration file format in foo at line 5: only one up migration allowed"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_migration_multiple_downs() -> Result<()> {
+        let migration_str = "
+-- up
+create table users (id int);
+
+-- down
+alter table users remove column id;
+
+-- down
+drop table users;
+"
+        .to_string();
+        let result = parse_migration("foo".to_string(), Cursor::new(migration_str));
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "bad migration file format in foo at line 8: only one down migration allowed"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_normal_migration() -> Result<()> {
+        let migration_str = "
+-- up
+create table users (
+  id int
+);
+
+-- down
+drop table users;
+"
+        .to_string();
+        let result = parse_migration("foo".to_string(), Cursor::new(migration_str));
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.ok().unwrap(),
+            Migration {
+                name: "foo".to_string(),
+                up_sql: "create table users (
+  id int
+);"
+                .to_string(),
+                down_sql: "drop table users;".to_string(),
+                kind: MigrationKind::Versioned,
+                lines: SourceLines { up: 3, down: 8 },
+                timeouts: Timeouts::default(),
+                template: false,
+                lint_ignore: Vec::new(),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_migration_down_before_up() -> Result<()> {
+        let migration_str = "-- down\ndrop table users;\n-- up\ncreate table users (id int);\n";
+        let result = parse_migration("foo".to_string(), Cursor::new(migration_str));
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "bad migration file format in foo at line 3: up migration must come first"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_migration_marker_variants() -> Result<()> {
+        let migration_str =
+            "--UP  \r\ncreate table users (\r\n  id int\r\n);\r\n\r\n  -- Down\r\ndrop table users;\r\n";
+        let result = parse_migration("foo".to_string(), Cursor::new(migration_str))?;
+
+        assert_eq!(result.up_sql, "create table users (\n  id int\n);");
+        assert_eq!(result.down_sql, "drop table users;");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_migration_ignores_markers_in_literals() -> Result<()> {
+        let migration_str = "
+-- up
+create function f() returns text as $$
+-- down
+select 'x';
+$$ language sql;
+select '
+-- up
+';
+/*
+-- down
+*/
+
+-- down
+drop function f;
+";
+        let result = parse_migration("foo".to_string(), Cursor::new(migration_str))?;
+
+        assert_eq!(
+            result.up_sql,
+            "create function f() returns text as $$
+-- down
+select 'x';
+$$ language sql;
+select '
+-- up
+';
+/*
+-- down
+*/"
+        );
+        assert_eq!(result.down_sql, "drop function f;");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_repeatable_migration() -> Result<()> {
+        let migration_str = "
+create or replace view active_users as select * from users where active;
+"
+        .to_string();
+        let result =
+            parse_repeatable_migration("views.sql".to_string(), Cursor::new(migration_str))?;
+
+        assert_eq!(
+            result,
+            Migration {
+                name: "views.sql".to_string(),
+                up_sql: "create or replace view active_users as select * from users where active;"
+                    .to_string(),
+                down_sql: "".to_string(),
+                kind: MigrationKind::Repeatable,
+                lines: SourceLines { up: 2, down: 1 },
+                timeouts: Timeouts::default(),
+                template: false,
+                lint_ignore: Vec::new(),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_repeatable_migration_empty() -> Result<()> {
+        let result = parse_repeatable_migration("views.sql".to_string(), Cursor::new("\n"));
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "bad migration file format in views.sql: repeatable migration is empty"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_directives() -> Result<()> {
+        let migration_str = "-- fly:lock-timeout 5s
+-- fly:lint-ignore FLY002
+-- up
+-- fly:statement-timeout 1500
+create index users_name on users (name);
+select '
+-- fly:lock-timeout forever
+-- fly:lint-ignore FLY003
+';
+-- down
+drop index users_name;
+";
+        let migration = parse_migration("foo.sql".to_string(), Cursor::new(migration_str))?;
+        assert_eq!(
+            migration.timeouts,
+            Timeouts {
+                lock_timeout: Some(Duration::from_secs(5)),
+                statement_timeout: Some(Duration::from_millis(1500)),
+            }
+        );
+        assert!(!migration.template);
+        assert_eq!(migration.lint_ignore, ["FLY002"]);
+
+        let migration = parse_migration(
+            "foo.sql".to_string(),
+            Cursor::new("-- fly:template\n-- up\nselect ${x};\n-- down\n"),
+        )?;
+        assert!(migration.template);
+
+        let result = parse_migration(
+            "foo.sql".to_string(),
+            Cursor::new(
+                "-- up
+-- fly:lock-timeout soon
+select 1;
+-- down
+",
+            ),
+        );
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "bad migration file format in foo.sql at line 2: invalid duration \"soon\" for fly:lock-timeout"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_directory_migration() -> Result<()> {
+        let migrate_dir = tempdir()?;
+        fs::write(
+            migrate_dir.path().join("1-create-users.sql"),
+            "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+        )?;
+        let dir_migration = migrate_dir.path().join("2-create-posts");
+        fs::create_dir(&dir_migration)?;
+        fs::write(
+            dir_migration.join("up.sql"),
+            "create table posts (id int);\n",
+        )?;
+        fs::write(dir_migration.join("down.sql"), "drop table posts;\n")?;
+        fs::write(dir_migration.join("README.md"), "Adds posts.\n")?;
+        fs::create_dir(migrate_dir.path().join("notes"))?;
+
+        let mut migrations = list(migrate_dir.path())?;
+        migrations.sort();
+
+        assert_eq!(
+            migrations,
+            vec![
+                Migration {
+                    name: "1-create-users.sql".to_string(),
+                    up_sql: "create table users (id int);".to_string(),
+                    down_sql: "drop table users;".to_string(),
+                    kind: MigrationKind::Versioned,
+                    lines: SourceLines { up: 2, down: 4 },
+                    timeouts: Timeouts::default(),
+                    template: false,
+                    lint_ignore: Vec::new(),
+                },
+                Migration {
+                    name: "2-create-posts".to_string(),
+                    up_sql: "create table posts (id int);".to_string(),
+                    down_sql: "drop table posts;".to_string(),
+                    kind: MigrationKind::Versioned,
+                    lines: SourceLines { up: 1, down: 1 },
+                    timeouts: Timeouts::default(),
+                    template: false,
+                    lint_ignore: Vec::new(),
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_directory_migration_missing_down() -> Result<()> {
+        let migrate_dir = tempdir()?;
+        let dir_migration = migrate_dir.path().join("1-create-posts");
+        fs::create_dir(&dir_migration)?;
+        fs::write(
+            dir_migration.join("up.sql"),
+            "create table posts (id int);\n",
+        )?;
+
+        let result = list(migrate_dir.path());
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "bad migration file format in 1-create-posts: directory must contain both up.sql and down.sql"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_repeatable_directory_migration() -> Result<()> {
+        let migrate_dir = tempdir()?;
+        let dir_migration = migrate_dir.path().join("repeatable").join("views");
+        fs::create_dir_all(&dir_migration)?;
+        fs::write(
+            dir_migration.join("up.sql"),
+            "create or replace view active_users as select * from users;\n",
+        )?;
+        fs::write(dir_migration.join("down.sql"), "drop view active_users;\n")?;
+
+        let result = list(migrate_dir.path());
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "bad migration file format in views: repeatable migrations must be single .sql files"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_nested_directories() -> Result<()> {
+        let migrate_dir = tempdir()?;
+        let dir_2023 = migrate_dir.path().join("2023");
+        let dir_2024 = migrate_dir.path().join("2024").join("billing");
+        let repeatable_dir = migrate_dir.path().join("repeatable").join("views");
+        fs::create_dir_all(&dir_2023)?;
+        fs::create_dir_all(&dir_2024)?;
+        fs::create_dir_all(&repeatable_dir)?;
+        fs::create_dir_all(migrate_dir.path().join(".git"))?;
+        fs::write(
+            dir_2024.join("3-create-invoices.sql"),
+            "-- up\ncreate table invoices (id int);\n-- down\ndrop table invoices;\n",
+        )?;
+        fs::write(
+            dir_2023.join("1-create-users.sql"),
+            "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n",
+        )?;
+        fs::write(
+            migrate_dir.path().join("2-create-posts.sql"),
+            "-- up\ncreate table posts (id int);\n-- down\ndrop table posts;\n",
+        )?;
+        fs::write(
+            repeatable_dir.join("active-users.sql"),
+            "create or replace view active_users as select * from users;\n",
+        )?;
+        fs::write(
+            migrate_dir.path().join(".git").join("0-hidden.sql"),
+            "not a migration",
+        )?;
+
+        let names = list(migrate_dir.path())?
+            .into_iter()
+            .map(|m| (m.name, m.kind))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            names,
+            vec![
+                ("1-create-users.sql".to_string(), MigrationKind::Versioned),
+                ("2-create-posts.sql".to_string(), MigrationKind::Versioned),
+                (
+                    "3-create-invoices.sql".to_string(),
+                    MigrationKind::Versioned
+                ),
+                ("active-users.sql".to_string(), MigrationKind::Repeatable),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_duplicate_names() -> Result<()> {
+        let migrate_dir = tempdir()?;
+        let team_a = migrate_dir.path().join("team-a");
+        let team_b = migrate_dir.path().join("team-b");
+        fs::create_dir_all(&team_a)?;
+        fs::create_dir_all(&team_b)?;
+        let contents = "-- up\ncreate table users (id int);\n-- down\ndrop table users;\n";
+        fs::write(team_a.join("1-create-users.sql"), contents)?;
+        fs::write(team_b.join("1-create-users.sql"), contents)?;
+
+        let result = list(migrate_dir.path());
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            format!(
+                "duplicate migration 1-create-users.sql: found at {} and {}",
+                team_a.join("1-create-users.sql").display(),
+                team_b.join("1-create-users.sql").display()
+            )
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+//! Migration types and file parsing for fly-migrate. Use them through the
+//! `fly` crate, which re-exports these modules; they live here so that
+//! `fly-migrate-macros` can parse migrations at compile time without
+//! depending on `fly` itself.
+
+pub mod error;
+pub mod file;
+pub mod migration;
+pub mod naming;
+#[doc(hidden)]
+pub mod sql;
@@ -0,0 +1,206 @@
+use crate::error::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MigrationKind {
+    /// Applied once, in name order, and rolled back with its down sql.
+    Versioned,
+    /// Re-applied after all versioned migrations whenever its contents change.
+    Repeatable,
+}
+
+impl MigrationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationKind::Versioned => "versioned",
+            MigrationKind::Repeatable => "repeatable",
+        }
+    }
+}
+
+impl FromStr for MigrationKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "versioned" => Ok(MigrationKind::Versioned),
+            "repeatable" => Ok(MigrationKind::Repeatable),
+            _ => Err(Error::UnknownMigrationKind {
+                kind: s.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Up,
+    Down,
+}
+
+impl Display for Section {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Section::Up => write!(f, "up"),
+            Section::Down => write!(f, "down"),
+        }
+    }
+}
+
+/// The lines of the source file on which the up and down sql begin, counted
+/// from 1. Used to point errors at the right place in the file; for
+/// migrations read back from the database both are 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLines {
+    pub up: usize,
+    pub down: usize,
+}
+
+impl Default for SourceLines {
+    fn default() -> Self {
+        SourceLines { up: 1, down: 1 }
+    }
+}
+
+/// Postgres timeouts to run a migration with. `None` leaves the server's
+/// setting alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timeouts {
+    /// How long a statement may wait for a lock, e.g. behind a long running
+    /// transaction, before failing.
+    pub lock_timeout: Option<Duration>,
+    /// How long a single statement may run before it is cancelled.
+    pub statement_timeout: Option<Duration>,
+}
+
+impl Timeouts {
+    /// These timeouts, falling back to `defaults` for any that aren't set.
+    pub fn or(&self, defaults: &Timeouts) -> Timeouts {
+        Timeouts {
+            lock_timeout: self.lock_timeout.or(defaults.lock_timeout),
+            statement_timeout: self.statement_timeout.or(defaults.statement_timeout),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    pub up_sql: String,
+    pub down_sql: String,
+    pub name: String,
+    pub kind: MigrationKind,
+    pub lines: SourceLines,
+    /// Overrides set with `-- fly:lock-timeout` or `-- fly:statement-timeout`
+    /// directives in the migration file.
+    pub timeouts: Timeouts,
+    /// Whether the migration has a `-- fly:template` directive, so that
+    /// placeholders in its sql are substituted before it is run.
+    pub template: bool,
+    /// Lint rule ids listed in `-- fly:lint-ignore` directives, which `fly
+    /// lint` doesn't report for this migration.
+    pub lint_ignore: Vec<String>,
+}
+
+impl Migration {
+    /// A versioned migration with the given sql and no directives, e.g. to
+    /// pass to `MigratorBuilder::migrations`.
+    pub fn new(name: impl AsRef<str>, up_sql: impl AsRef<str>, down_sql: impl AsRef<str>) -> Self {
+        Migration {
+            up_sql: up_sql.as_ref().to_string(),
+            down_sql: down_sql.as_ref().to_string(),
+            name: name.as_ref().to_string(),
+            kind: MigrationKind::Versioned,
+            lines: SourceLines::default(),
+            timeouts: Timeouts::default(),
+            template: false,
+            lint_ignore: Vec::new(),
+        }
+    }
+
+    /// A repeatable migration with the given sql and no directives.
+    pub fn repeatable(name: impl AsRef<str>, up_sql: impl AsRef<str>) -> Self {
+        Migration {
+            kind: MigrationKind::Repeatable,
+            ..Migration::new(name, up_sql, "")
+        }
+    }
+
+    pub fn sql(&self, section: Section) -> &str {
+        match section {
+            Section::Up => &self.up_sql,
+            Section::Down => &self.down_sql,
+        }
+    }
+
+    pub fn first_line(&self, section: Section) -> usize {
+        match section {
+            Section::Up => self.lines.up,
+            Section::Down => self.lines.down,
+        }
+    }
+
+    pub fn is_repeatable(&self) -> bool {
+        self.kind == MigrationKind::Repeatable
+    }
+
+    /// Hex-encoded SHA-256 of the up and down sql.
+    pub fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.up_sql.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.down_sql.as_bytes());
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+impl PartialOrd for Migration {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Migration {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.kind
+            .cmp(&other.kind)
+            .then_with(|| self.name.cmp(&other.name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationMeta {
+    pub id: i32,
+    pub created_at: SystemTime,
+    /// The operating system user and host that applied the migration.
+    pub applied_by: Option<String>,
+    pub applied_host: Option<String>,
+    /// The version of fly that applied the migration.
+    pub fly_version: Option<String>,
+    /// How long the up sql took to run.
+    pub execution_time: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationWithMeta {
+    pub migration: Migration,
+    pub meta: MigrationMeta,
+}
+
+/// Parses a duration such as `5s`, `500ms` or `1min`. Like postgres, a bare
+/// number is taken as milliseconds.
+pub fn parse_duration(s: &str) -> std::result::Result<Duration, humantime::DurationError> {
+    if let Ok(ms) = s.parse::<u64>() {
+        return Ok(Duration::from_millis(ms));
+    }
+    humantime::parse_duration(s)
+}
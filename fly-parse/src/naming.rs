@@ -0,0 +1,218 @@
+use crate::error::{Error, Result};
+use crate::migration::Migration;
+use std::collections::HashMap;
+
+/// Rules that migration names must follow. The default is
+/// [`NamingPolicy::relaxed`], and [`NamingPolicy::strict`] is the policy
+/// enabled with `MIGRATE_NAMING=strict`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamingPolicy {
+    /// Require versioned migrations to start with a numeric version followed
+    /// by `-` or `_`, e.g. `1711200000-create-users.sql`. Repeatable
+    /// migrations are exempt.
+    pub require_version: bool,
+    /// The minimum number of digits in a version. Defaults to 10, the length
+    /// of the unix timestamps generated by `fly new`. `fly convert` pads
+    /// versions to this many digits under either policy.
+    pub min_version_digits: usize,
+    /// Characters allowed in names besides ASCII letters and digits. `None`
+    /// allows any character.
+    pub extra_chars: Option<String>,
+}
+
+impl Default for NamingPolicy {
+    fn default() -> Self {
+        NamingPolicy::relaxed()
+    }
+}
+
+impl NamingPolicy {
+    /// Versioned migrations must start with a version of at least 10 digits,
+    /// and names may only contain letters, digits, `-` and `_`.
+    pub fn strict() -> Self {
+        NamingPolicy {
+            require_version: true,
+            min_version_digits: 10,
+            extra_chars: Some("-_".to_string()),
+        }
+    }
+
+    /// A policy that accepts any name, and fly's default.
+    pub fn relaxed() -> Self {
+        NamingPolicy {
+            require_version: false,
+            extra_chars: None,
+            ..NamingPolicy::strict()
+        }
+    }
+
+    /// Checks every migration's name, and that no two versioned migrations
+    /// share a version. Under either policy, names that start with a version
+    /// are checked for duplicates.
+    pub fn check(&self, migrations: &[Migration]) -> Result<()> {
+        let mut versions: HashMap<&str, &str> = HashMap::new();
+        for migration in migrations {
+            self.check_name(&migration.name, !migration.is_repeatable())?;
+            if migration.is_repeatable() {
+                continue;
+            }
+            let Some(version) = version(&migration.name) else {
+                continue;
+            };
+            if let Some(first) = versions.insert(version, &migration.name) {
+                return Err(Error::DuplicateVersion {
+                    version: version.to_string(),
+                    first: first.to_string(),
+                    second: migration.name.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a single migration name. `versioned` should be false for
+    /// repeatable migrations, which don't need a version.
+    pub fn check_name(&self, name: &str, versioned: bool) -> Result<()> {
+        let stem = name.strip_suffix(".sql").unwrap_or(name);
+        let invalid = |reason: String| Error::InvalidMigrationName {
+            name: name.to_string(),
+            reason,
+        };
+
+        if let Some(extra_chars) = &self.extra_chars {
+            if let Some(c) = stem
+                .chars()
+                .find(|c| !c.is_ascii_alphanumeric() && !extra_chars.contains(*c))
+            {
+                return Err(invalid(format!("character {:?} is not allowed", c)));
+            }
+        }
+
+        if self.require_version && versioned {
+            match version(name) {
+                None => {
+                    return Err(invalid(
+                        "must start with a numeric version followed by '-' or '_'".to_string(),
+                    ))
+                }
+                Some(version) if version.len() < self.min_version_digits => {
+                    return Err(invalid(format!(
+                        "version {} must have at least {} digits",
+                        version, self.min_version_digits
+                    )))
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The numeric version at the start of a migration name, e.g. `1711200000`
+/// for `1711200000-create-users.sql`.
+pub fn version(name: &str) -> Option<&str> {
+    let end = name.find(|c: char| !c.is_ascii_digit())?;
+    let (version, rest) = name.split_at(end);
+    if version.is_empty() || !(rest.starts_with('-') || rest.starts_with('_')) {
+        return None;
+    }
+    Some(version)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert_eq!(version("1711200000-create-users.sql"), Some("1711200000"));
+        assert_eq!(
+            version("20240101120000_create_users"),
+            Some("20240101120000")
+        );
+        assert_eq!(version("fix.sql"), None);
+        assert_eq!(version("1711200000.sql"), None);
+        assert_eq!(version("-create-users.sql"), None);
+    }
+
+    #[test]
+    fn test_check_name_strict_policy() {
+        let policy = NamingPolicy::strict();
+        assert!(policy
+            .check_name("1711200000-create-users.sql", true)
+            .is_ok());
+        assert!(policy.check_name("1711200000-create-posts", true).is_ok());
+        assert!(policy.check_name("active-users.sql", false).is_ok());
+
+        assert_eq!(
+            policy.check_name("fix.sql", true).err().unwrap().to_string(),
+            "invalid migration name fix.sql: must start with a numeric version followed by '-' or '_'"
+        );
+        assert_eq!(
+            policy
+                .check_name("2024-foo.sql", true)
+                .err()
+                .unwrap()
+                .to_string(),
+            "invalid migration name 2024-foo.sql: version 2024 must have at least 10 digits"
+        );
+        assert_eq!(
+            policy
+                .check_name("1711200000-create users.sql", true)
+                .err()
+                .unwrap()
+                .to_string(),
+            "invalid migration name 1711200000-create users.sql: character ' ' is not allowed"
+        );
+    }
+
+    #[test]
+    fn test_check_name_relaxed_policy() {
+        let policy = NamingPolicy::relaxed();
+        assert!(policy.check_name("fix.sql", true).is_ok());
+        assert!(policy.check_name("2024 foo.sql", true).is_ok());
+    }
+
+    #[test]
+    fn test_check_duplicate_versions() {
+        let migration = |name: &str| Migration::new(name, "", "");
+        let policy = NamingPolicy::strict();
+
+        assert!(policy
+            .check(&[
+                migration("1711200000-create-users.sql"),
+                migration("1711200001-create-posts.sql"),
+            ])
+            .is_ok());
+        assert_eq!(
+            policy
+                .check(&[
+                    migration("1711200000-create-users.sql"),
+                    migration("1711200000-create-posts.sql"),
+                ])
+                .err()
+                .unwrap()
+                .to_string(),
+            "migrations 1711200000-create-users.sql and 1711200000-create-posts.sql share version 1711200000"
+        );
+    }
+
+    #[test]
+    fn test_check_duplicate_versions_relaxed_policy() {
+        let migration = |name: &str| Migration::new(name, "", "");
+        let policy = NamingPolicy::relaxed();
+
+        // Names without a version can't collide.
+        assert!(policy
+            .check(&[migration("fix.sql"), migration("fix-again.sql")])
+            .is_ok());
+        assert_eq!(
+            policy
+                .check(&[migration("1711200000-a.sql"), migration("1711200000-b.sql")])
+                .err()
+                .unwrap()
+                .to_string(),
+            "migrations 1711200000-a.sql and 1711200000-b.sql share version 1711200000"
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! A minimal lexer for postgres sql, just enough to tell ordinary code apart
+//! from comments, string literals, quoted identifiers and dollar-quoted
+//! bodies.
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// Ordinary sql, including whitespace.
+    Code,
+    /// A `--` or `/* */` comment. Line comments stop before the newline.
+    Comment,
+    /// A string literal, quoted identifier or dollar-quoted body, including
+    /// its delimiters.
+    Quoted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub kind: SegmentKind,
+    /// Byte range of the segment in the source.
+    pub range: Range<usize>,
+}
+
+/// Splits `sql` into contiguous code, comment and quoted segments. An
+/// unterminated comment or literal runs to the end of the input.
+pub fn segments(sql: &str) -> Vec<Segment> {
+    let bytes = sql.as_bytes();
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let (kind, end) = match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                let end = sql[i..].find('\n').map_or(sql.len(), |n| i + n);
+                (SegmentKind::Comment, end)
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                (SegmentKind::Comment, block_comment_end(bytes, i))
+            }
+            b'\'' => {
+                let escapes = i > 0
+                    && matches!(bytes[i - 1], b'e' | b'E')
+                    && (i < 2 || !is_ident_byte(bytes[i - 2]));
+                (SegmentKind::Quoted, quoted_end(bytes, i, b'\'', escapes))
+            }
+            b'"' => (SegmentKind::Quoted, quoted_end(bytes, i, b'"', false)),
+            b'$' if i == 0 || !is_ident_byte(bytes[i - 1]) => match dollar_tag(sql, i) {
+                Some(tag) => {
+                    let body = i + tag.len();
+                    let end = sql[body..]
+                        .find(tag)
+                        .map_or(sql.len(), |n| body + n + tag.len());
+                    (SegmentKind::Quoted, end)
+                }
+                None => {
+                    i += 1;
+                    continue;
+                }
+            },
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        push_segment(SegmentKind::Code, start..i, &mut segments);
+        push_segment(kind, i..end, &mut segments);
+        start = end;
+        i = end;
+    }
+    push_segment(SegmentKind::Code, start..sql.len(), &mut segments);
+    segments
+}
+
+/// Whether byte offset `at` is outside of any comment or quoted segment, or
+/// exactly at the start of one.
+pub fn starts_in_code(segments: &[Segment], at: usize) -> bool {
+    segments
+        .iter()
+        .find(|s| s.range.contains(&at))
+        .is_none_or(|s| s.kind == SegmentKind::Code || s.range.start == at)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement<'a> {
+    /// The statement's text, trimmed, including its terminating semicolon.
+    pub sql: &'a str,
+    /// First and last line of the statement, counted from 1.
+    pub first_line: usize,
+    pub last_line: usize,
+}
+
+/// Splits `sql` into statements at semicolons in ordinary code. Semicolons
+/// inside parentheses (e.g. `CREATE RULE ... DO (...; ...)`) and inside
+/// `BEGIN ATOMIC ... END` function bodies don't end a statement. Pieces
+/// containing only whitespace and comments are dropped.
+pub fn statements(sql: &str) -> Vec<Statement<'_>> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut has_code = false;
+    let mut parens = 0i32;
+    // Nesting depth inside BEGIN ATOMIC bodies; CASE ... END nests too.
+    let mut atomic = 0i32;
+    let mut previous_word = String::new();
+
+    for segment in segments(sql) {
+        if segment.kind == SegmentKind::Quoted {
+            has_code = true;
+        }
+        if segment.kind != SegmentKind::Code {
+            continue;
+        }
+        let code = &sql[segment.range.clone()];
+        let mut word_start = None;
+        for (i, c) in code
+            .char_indices()
+            .chain(std::iter::once((code.len(), ' ')))
+        {
+            if c.is_alphanumeric() || c == '_' {
+                word_start.get_or_insert(i);
+                has_code = true;
+                continue;
+            }
+            if let Some(word_start) = word_start.take() {
+                let word = code[word_start..i].to_ascii_lowercase();
+                if (word == "atomic" && previous_word == "begin") || (atomic > 0 && word == "case")
+                {
+                    atomic += 1;
+                } else if atomic > 0 && word == "end" {
+                    atomic -= 1;
+                }
+                previous_word = word;
+            }
+            if i == code.len() {
+                break;
+            }
+            match c {
+                '(' => parens += 1,
+                ')' => parens -= 1,
+                ';' if parens <= 0 && atomic <= 0 => {
+                    let end = segment.range.start + i + 1;
+                    if has_code {
+                        statements.push(statement(sql, start..end));
+                    }
+                    start = end;
+                    has_code = false;
+                    parens = 0;
+                    continue;
+                }
+                _ => {}
+            }
+            if !c.is_whitespace() {
+                has_code = true;
+            }
+        }
+    }
+    if has_code {
+        statements.push(statement(sql, start..sql.len()));
+    }
+    statements
+}
+
+fn statement(sql: &str, range: Range<usize>) -> Statement<'_> {
+    let text = &sql[range.clone()];
+    let leading = text.len() - text.trim_start().len();
+    let trimmed = text.trim();
+    let first_line = line_at(sql, range.start + leading);
+    let last_line = first_line + trimmed.matches('\n').count();
+    Statement {
+        sql: trimmed,
+        first_line,
+        last_line,
+    }
+}
+
+/// The line, counted from 1, of byte offset `at`.
+pub fn line_at(sql: &str, at: usize) -> usize {
+    sql[..at].matches('\n').count() + 1
+}
+
+fn push_segment(kind: SegmentKind, range: Range<usize>, segments: &mut Vec<Segment>) {
+    if !range.is_empty() {
+        segments.push(Segment { kind, range });
+    }
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+// Nested block comments are allowed in postgres.
+fn block_comment_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            depth += 1;
+            i += 2;
+        } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+// A doubled quote character is an escaped quote. In `E'...'` strings a
+// backslash also escapes the next character.
+fn quoted_end(bytes: &[u8], start: usize, quote: u8, escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+        } else if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+// The opening delimiter of a dollar-quoted string starting at `start`, e.g.
+// `$$` or `$body$`.
+fn dollar_tag(sql: &str, start: usize) -> Option<&str> {
+    let rest = &sql[start + 1..];
+    let len = rest.find('$')?;
+    let tag = &rest[..len];
+    let valid = tag
+        .chars()
+        .next()
+        .is_none_or(|c| c.is_alphabetic() || c == '_')
+        && tag.chars().all(|c| c.is_alphanumeric() || c == '_');
+    valid.then(|| &sql[start..start + len + 2])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kinds(sql: &str) -> Vec<(SegmentKind, &str)> {
+        segments(sql)
+            .into_iter()
+            .map(|s| (s.kind, &sql[s.range]))
+            .collect()
+    }
+
+    #[test]
+    fn test_segments() {
+        use SegmentKind::*;
+
+        assert_eq!(
+            kinds("select 'a;b', \"c\" -- d\nfrom t; /* e /* f */ */"),
+            vec![
+                (Code, "select "),
+                (Quoted, "'a;b'"),
+                (Code, ", "),
+                (Quoted, "\"c\""),
+                (Code, " "),
+                (Comment, "-- d"),
+                (Code, "\nfrom t; "),
+                (Comment, "/* e /* f */ */"),
+            ]
+        );
+        assert_eq!(
+            kinds("select 'it''s', E'\\'', $1"),
+            vec![
+                (Code, "select "),
+                (Quoted, "'it''s'"),
+                (Code, ", E"),
+                (Quoted, "'\\''"),
+                (Code, ", $1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_segments_dollar_quoted() {
+        use SegmentKind::*;
+
+        assert_eq!(
+            kinds("as $body$ select '$$'; $body$ language sql"),
+            vec![
+                (Code, "as "),
+                (Quoted, "$body$ select '$$'; $body$"),
+                (Code, " language sql"),
+            ]
+        );
+        assert_eq!(
+            kinds("as $$\n-- down\n$$;"),
+            vec![(Code, "as "), (Quoted, "$$\n-- down\n$$"), (Code, ";")]
+        );
+    }
+
+    fn split(sql: &str) -> Vec<(&str, usize, usize)> {
+        statements(sql)
+            .into_iter()
+            .map(|s| (s.sql, s.first_line, s.last_line))
+            .collect()
+    }
+
+    #[test]
+    fn test_statements() {
+        assert_eq!(
+            split("create table a (x int);\n\n-- comment; here\ninsert into a\n  values (';');\n-- trailing"),
+            vec![
+                ("create table a (x int);", 1, 1),
+                ("-- comment; here\ninsert into a\n  values (';');", 3, 5),
+            ]
+        );
+        assert_eq!(
+            split("select 1;;select 2"),
+            vec![("select 1;", 1, 1), ("select 2", 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_statements_function_bodies() {
+        assert_eq!(
+            split(
+                "create function f() returns int as $$ select 1; $$ language sql;
+create rule r as on insert to a do also (insert into b values (1); insert into c values (1));
+create function g() returns int begin atomic
+  select case when true then 1 end;
+  select 2;
+end;
+select 3;"
+            ),
+            vec![
+                (
+                    "create function f() returns int as $$ select 1; $$ language sql;",
+                    1,
+                    1
+                ),
+                (
+                    "create rule r as on insert to a do also (insert into b values (1); insert into c values (1));",
+                    2,
+                    2
+                ),
+                (
+                    "create function g() returns int begin atomic
+  select case when true then 1 end;
+  select 2;
+end;",
+                    3,
+                    6
+                ),
+                ("select 3;", 7, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_starts_in_code() {
+        let sql = "a 'b\nc' -- d\ne";
+        let segments = segments(sql);
+        assert!(starts_in_code(&segments, 0));
+        assert!(starts_in_code(&segments, 2));
+        assert!(!starts_in_code(&segments, 5));
+        assert!(starts_in_code(&segments, 8));
+        assert!(!starts_in_code(&segments, 9));
+        assert!(starts_in_code(&segments, 13));
+    }
+}